@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::duelingbook::{DuelingBookCard, DuelingBookDeck};
+
+/// A single card in a deck, reduced to what deck validation needs.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct DeckCard {
+    /// The DuelingBook card id, when the deck came from DuelingBook.
+    pub id: Option<u32>,
+    pub name: String,
+    /// The author of a custom DuelingBook card.
+    pub username: Option<String>,
+}
+
+impl DeckCard {
+    /// The bolded card name, noting the author of custom cards.
+    pub fn describe(&self) -> String {
+        match &self.username {
+            Some(name) => format!("**{}** *(custom by: {})*", self.name, name),
+            None => format!("**{}**", self.name),
+        }
+    }
+}
+
+/// A custom card the format permits even though it isn't in the card pool,
+/// identified either by its DuelingBook id or by its name and author.
+#[derive(Debug, Deserialize)]
+pub struct AllowedCustom {
+    pub id: Option<u32>,
+    pub name: Option<String>,
+    pub username: Option<String>,
+}
+
+impl AllowedCustom {
+    pub fn allows(&self, card: &DeckCard) -> bool {
+        if card.username.is_none() {
+            return false;
+        }
+        match (self.id, &self.name, &self.username) {
+            (Some(id), _, _) => card.id == Some(id),
+            (None, Some(name), Some(username)) => {
+                *name == card.name && card.username.as_ref() == Some(username)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A deck from any of the supported sources.
+#[derive(Debug, Default)]
+pub struct Deck {
+    /// The DuelingBook deck id, when the deck came from DuelingBook.
+    pub id: Option<u32>,
+    pub main: Vec<DeckCard>,
+    pub side: Vec<DeckCard>,
+    pub extra: Vec<DeckCard>,
+}
+
+impl Deck {
+    /// Build a deck from lists of passcodes, naming each card via `names`.
+    /// Passcodes that aren't in `names` are kept as placeholder cards so
+    /// they are reported as invalid rather than silently dropped.
+    pub fn from_passcodes(
+        names: &HashMap<u32, &str>,
+        main: &[u32],
+        side: &[u32],
+        extra: &[u32],
+    ) -> Deck {
+        let resolve = |passcodes: &[u32]| {
+            passcodes
+                .iter()
+                .map(|passcode| DeckCard {
+                    id: None,
+                    name: match names.get(passcode) {
+                        Some(name) => name.to_string(),
+                        None => format!("Unknown card ({passcode})"),
+                    },
+                    username: None,
+                })
+                .collect()
+        };
+        Deck {
+            id: None,
+            main: resolve(main),
+            side: resolve(side),
+            extra: resolve(extra),
+        }
+    }
+}
+
+impl From<&DuelingBookCard> for DeckCard {
+    fn from(card: &DuelingBookCard) -> DeckCard {
+        DeckCard {
+            id: Some(card.id),
+            name: card.name.clone(),
+            username: card.username.clone(),
+        }
+    }
+}
+
+impl From<DuelingBookDeck> for Deck {
+    fn from(deck: DuelingBookDeck) -> Deck {
+        Deck {
+            id: Some(deck.id),
+            main: deck.main.iter().map(DeckCard::from).collect(),
+            side: deck.side.iter().map(DeckCard::from).collect(),
+            extra: deck.extra.iter().map(DeckCard::from).collect(),
+        }
+    }
+}
@@ -1,8 +1,10 @@
 #![allow(unused)]
 
 use anyhow::{anyhow, Context};
-use reqwest::IntoUrl;
-use serde::Deserialize;
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
 
 const LOAD_DECK: &str = "https://www.duelingbook.com/php-scripts/load-deck.php";
 
@@ -43,8 +45,11 @@ pub struct DuelingBookDeck {
     pub action: String,
     pub id: u32,
     pub name: String,
+    #[serde(default, deserialize_with = "null_as_empty")]
     pub main: Vec<DuelingBookCard>,
+    #[serde(default, deserialize_with = "null_as_empty")]
     pub side: Vec<DuelingBookCard>,
+    #[serde(default, deserialize_with = "null_as_empty")]
     pub extra: Vec<DuelingBookCard>,
     pub legality: String,
     pub tcg: String,
@@ -52,23 +57,177 @@ pub struct DuelingBookDeck {
     pub links: String,
 }
 
+/// DuelingBook sends `null` rather than `[]` for some empty zones.
+fn null_as_empty<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// What DuelingBook sends instead of a deck when it can't load one, such as
+/// `{"action": "Error", "message": "..."}`.
+#[derive(Debug, Deserialize)]
+struct DuelingBookError {
+    action: Option<String>,
+    error: Option<String>,
+    message: Option<String>,
+}
+
+impl DuelingBookError {
+    /// The reason given, if the payload looks like an error at all.
+    fn reason(self) -> Option<String> {
+        self.error.or(self.message).or_else(|| {
+            self.action
+                .filter(|action| action.eq_ignore_ascii_case("error"))
+        })
+    }
+}
+
 impl DuelingBookDeck {
     // https://www.duelingbook.com/deck?id=16249952
-    pub async fn get_deck<T: IntoUrl>(deck_url: T) -> Result<DuelingBookDeck, anyhow::Error> {
-        let url = deck_url.into_url()?;
-        let mut pairs = url.query_pairs();
-        let (_, id) = pairs
-            .find(|(k, _v)| k == "id")
-            .ok_or_else(|| anyhow!("no id in duelingbook URL"))?;
+    pub async fn get_deck<T: AsRef<str>>(deck_url: T) -> Result<DuelingBookDeck, anyhow::Error> {
+        let text = Self::get_deck_text(deck_url).await?;
+        Self::parse(&text)
+    }
+
+    /// Parse a `load-deck.php` response, telling DuelingBook's own errors
+    /// apart from responses that are not decks at all.
+    fn parse(text: &str) -> Result<DuelingBookDeck, anyhow::Error> {
+        match serde_json::from_str(text) {
+            Ok(deck) => Ok(deck),
+            Err(e) => {
+                if let Some(reason) = serde_json::from_str::<DuelingBookError>(text)
+                    .ok()
+                    .and_then(DuelingBookError::reason)
+                {
+                    return Err(anyhow!(
+                        "This deck is private or does not exist (DuelingBook said: {reason})"
+                    ));
+                }
+                Err(e).with_context(|| format!("While parsing `{text}`"))
+            }
+        }
+    }
+
+    /// Fetch the unparsed response body DuelingBook returns for a deck.
+    pub async fn get_deck_text<T: AsRef<str>>(deck_url: T) -> Result<String, anyhow::Error> {
+        let id = deck_id(deck_url.as_ref())?;
 
         let response = reqwest::Client::new()
             .post(LOAD_DECK)
-            .multipart(reqwest::multipart::Form::new().text("id", id.to_string()))
+            .multipart(reqwest::multipart::Form::new().text("id", id))
             .send()
             .await?;
 
-        let text = response.text().await?;
+        Ok(response.text().await?)
+    }
+}
+
+/// Find the deck id in whatever the user pasted: the `id` query pair, a
+/// `/deck/<id>` path, or failing those the first run of digits.
+fn deck_id(deck_url: &str) -> Result<String, anyhow::Error> {
+    let is_id = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    if let Ok(url) = reqwest::Url::parse(deck_url.trim()) {
+        if let Some((_, id)) = url.query_pairs().find(|(k, v)| k == "id" && is_id(v)) {
+            return Ok(id.into_owned());
+        }
+        if let Some(segments) = url.path_segments() {
+            let segments: Vec<_> = segments.collect();
+            if let Some(id) = segments
+                .windows(2)
+                .find(|pair| pair[0] == "deck" && is_id(pair[1]))
+                .map(|pair| pair[1])
+            {
+                return Ok(id.to_string());
+            }
+        }
+    }
+
+    static DIGITS: OnceLock<Regex> = OnceLock::new();
+    let digits = DIGITS.get_or_init(|| Regex::new(r"\d+").expect("Cannot compile digits re"));
+    digits
+        .find(deck_url)
+        .map(|id| id.as_str().to_string())
+        .ok_or_else(|| {
+            anyhow!(
+                "Could not find a deck id in `{deck_url}`. Use a link like \
+                 https://www.duelingbook.com/deck?id=<id> or \
+                 https://www.duelingbook.com/deck/<id>, or just the id"
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_deck_id() {
+        for link in [
+            "https://www.duelingbook.com/deck?id=12345",
+            "https://www.duelingbook.com/deck?foo=bar&id=12345",
+            "  https://www.duelingbook.com/deck/12345 ",
+            "https://duelingbook.com/deck/12345?view=1",
+            "12345",
+            "deck 12345 please",
+        ] {
+            assert_eq!(deck_id(link).unwrap(), "12345", "{link}");
+        }
+    }
+
+    #[test]
+    fn prefers_the_id_pair_over_other_digits() {
+        assert_eq!(
+            deck_id("https://www.duelingbook.com/deck?v=2&id=12345").unwrap(),
+            "12345"
+        );
+        assert!(deck_id("https://www.duelingbook.com/deck").is_err());
+    }
+
+    fn card_json(name: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": 1, "name": name, "treated_as": name, "effect": "", "pendulum_effect": "",
+            "card_type": "Monster", "monster_color": "Normal", "is_effect": 0, "type": "Spellcaster",
+            "attribute": "DARK", "level": 7, "ability": "", "flip": 0, "pendulum": 0, "scale": 0,
+            "arrows": "", "atk": "2500", "def": "2100", "tcg_limit": 3, "ocg_limit": 3,
+            "serial_number": "46986414", "tcg": 1, "ocg": 1, "rush": 0, "pic": "1", "hidden": 0,
+        })
+    }
+
+    fn deck_json(main: serde_json::Value, side: serde_json::Value) -> String {
+        serde_json::json!({
+            "action": "load deck", "id": 12345, "name": "Test", "main": main, "side": side,
+            "extra": null, "legality": "", "tcg": "", "ocg": "", "links": "",
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn null_zones_are_empty() {
+        let deck = DuelingBookDeck::parse(&deck_json(
+            serde_json::json!([card_json("Dark Magician")]),
+            serde_json::Value::Null,
+        ))
+        .unwrap();
+        assert_eq!(deck.main.len(), 1);
+        assert_eq!(deck.main[0].name, "Dark Magician");
+        assert!(deck.side.is_empty());
+        assert!(deck.extra.is_empty());
+    }
+
+    #[test]
+    fn error_payloads_are_reported_as_duelingbook_errors() {
+        let err = DuelingBookDeck::parse(r#"{"action": "Error", "message": "Deck is private"}"#)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "This deck is private or does not exist (DuelingBook said: Deck is private)"
+        );
+        assert!(err.downcast_ref::<serde_json::Error>().is_none());
 
-        serde_json::from_str(&text).with_context(|| format!("While parsing `{text}`"))
+        let err = DuelingBookDeck::parse("<html>Not found</html>").unwrap_err();
+        assert!(err.to_string().starts_with("While parsing"), "{err}");
     }
 }
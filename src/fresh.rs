@@ -0,0 +1,513 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::Duration,
+};
+
+use anyhow::anyhow;
+use chrono::Utc;
+use reqwest::{header, StatusCode};
+use serde::de::DeserializeOwned;
+use tokio::sync::Mutex;
+
+/// Cache validators from the last fetch, sent back on the next one so an
+/// unchanged source can answer `304 Not Modified` instead of the full body.
+#[derive(Debug, Clone, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// The outcome of refreshing a data source.
+pub enum Refresh<D> {
+    /// New data, along with the validators to send next time.
+    Updated(D, Validators),
+    /// The source reported that nothing changed since the last fetch.
+    NotModified,
+}
+
+impl<D> Refresh<D> {
+    pub fn map<E>(self, f: impl FnOnce(D) -> E) -> Refresh<E> {
+        match self {
+            Refresh::Updated(data, validators) => Refresh::Updated(f(data), validators),
+            Refresh::NotModified => Refresh::NotModified,
+        }
+    }
+}
+
+pub type RefreshFn<D> =
+    fn(Validators) -> Pin<Box<dyn Future<Output = Result<Refresh<D>, anyhow::Error>> + Send>>;
+
+pub struct FreshData<D> {
+    shared: Arc<Shared<D>>,
+}
+
+/// The state behind a [`FreshData`], shared with the background task that
+/// refreshes it.
+struct Shared<D> {
+    frequency: chrono::Duration,
+    refresh: RefreshFn<D>,
+    /// Swapped out whole on refresh, so readers only hold the lock long
+    /// enough to clone the `Arc` and a refresh never waits on them.
+    data: RwLock<Arc<D>>,
+    /// Unix timestamp in milliseconds, checked on every `get` without taking
+    /// any lock.
+    expires: AtomicI64,
+    /// Held by whichever task is refreshing, so only one refresh runs at a
+    /// time.
+    validators: Arc<Mutex<Validators>>,
+    generation: AtomicU64,
+    /// Unix timestamp in milliseconds, or [`NEVER`] until the first
+    /// successful load.
+    last_success: AtomicI64,
+}
+
+/// Stored as `last_success` while the data has never been loaded.
+const NEVER: i64 = i64::MIN;
+/// How long to wait after a failed refresh before trying again, so an
+/// outage doesn't turn every `get` into a fetch.
+const RETRY_DELAY: chrono::Duration = chrono::Duration::seconds(30);
+/// How long a fetch may take before it counts as failed.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl<D> FreshData<D> {
+    pub async fn new(
+        frequency: chrono::Duration,
+        refresh: RefreshFn<D>,
+    ) -> Result<FreshData<D>, anyhow::Error> {
+        let (data, validators) = match (refresh)(Validators::default()).await? {
+            Refresh::Updated(data, validators) => (data, validators),
+            // The first fetch sends no validators, so there is nothing the
+            // source could have compared against.
+            Refresh::NotModified => {
+                return Err(anyhow!(
+                    "The source reported no changes to a fetch without validators"
+                ))
+            }
+        };
+        Ok(FreshData::with_expiry(
+            frequency,
+            refresh,
+            data,
+            validators,
+            Utc::now() + frequency,
+            Some(Utc::now()),
+        ))
+    }
+
+    /// Like `new`, but try the first fetch up to `attempts` times, waiting
+    /// `backoff` after the first failure and twice as long after each one
+    /// after it, so a source that is briefly down at startup doesn't stop
+    /// the bot from starting.
+    pub async fn with_retries(
+        frequency: chrono::Duration,
+        refresh: RefreshFn<D>,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<FreshData<D>, anyhow::Error> {
+        let mut delay = backoff;
+        for attempt in 1.. {
+            match FreshData::new(frequency, refresh).await {
+                Ok(data) => return Ok(data),
+                Err(e) if attempt < attempts => {
+                    tracing::warn!(
+                        "Initial load failed (attempt {attempt}/{attempts}), \
+                         retrying in {delay:?}: {e:#}"
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                Err(e) => return Err(e.context(format!("Initial load failed {attempts} times"))),
+            }
+        }
+        unreachable!("the attempts never run out")
+    }
+
+    /// Start from placeholder `data` that is refreshed on first use, for
+    /// sources that may be unavailable at startup. Until a refresh succeeds
+    /// `last_success` is `None`, so callers can tell the placeholder apart
+    /// from real data.
+    pub fn stale(frequency: chrono::Duration, refresh: RefreshFn<D>, data: D) -> FreshData<D> {
+        FreshData::with_expiry(
+            frequency,
+            refresh,
+            data,
+            Validators::default(),
+            Utc::now(),
+            None,
+        )
+    }
+
+    fn with_expiry(
+        frequency: chrono::Duration,
+        refresh: RefreshFn<D>,
+        data: D,
+        validators: Validators,
+        expires: chrono::DateTime<Utc>,
+        last_success: Option<chrono::DateTime<Utc>>,
+    ) -> FreshData<D> {
+        FreshData {
+            shared: Arc::new(Shared {
+                frequency,
+                refresh,
+                data: RwLock::new(Arc::new(data)),
+                expires: AtomicI64::new(expires.timestamp_millis()),
+                validators: Arc::new(Mutex::new(validators)),
+                generation: AtomicU64::new(0),
+                last_success: AtomicI64::new(
+                    last_success.map_or(NEVER, |at| at.timestamp_millis()),
+                ),
+            }),
+        }
+    }
+
+    /// Bumped every time the data is refreshed, so anything derived from the
+    /// data can tell when it has gone stale.
+    pub fn generation(&self) -> u64 {
+        self.shared.generation.load(Ordering::Acquire)
+    }
+
+    /// When the data was last fetched or confirmed unchanged, or `None` if
+    /// it never has been and only the placeholder from `stale` is served.
+    pub fn last_success(&self) -> Option<chrono::DateTime<Utc>> {
+        match self.shared.last_success.load(Ordering::Acquire) {
+            NEVER => None,
+            millis => chrono::DateTime::from_timestamp_millis(millis),
+        }
+    }
+
+    /// When the data is due for a refresh. The refresh itself starts on the
+    /// first `get` after this.
+    pub fn expires(&self) -> chrono::DateTime<Utc> {
+        chrono::DateTime::from_timestamp_millis(self.shared.expires.load(Ordering::Acquire))
+            .unwrap_or_default()
+    }
+
+    /// Wait for any refresh in progress, then refresh the data if it is
+    /// still expired, for callers that would rather have fresh data than a
+    /// quick answer. Slash commands must defer before calling this, as a
+    /// slow source can take longer than the interaction allows.
+    pub async fn refreshed(&self) -> Arc<D> {
+        let mut validators = self.shared.validators.lock().await;
+        if self.shared.is_expired() {
+            self.shared.refresh(&mut validators).await;
+        }
+        drop(validators);
+        self.shared.current()
+    }
+}
+
+impl<D: Send + Sync + 'static> FreshData<D> {
+    /// Get the data without waiting on the source. If it has expired a
+    /// refresh is started in the background and the current data served
+    /// meanwhile; if the refresh fails the stale data stays and the refresh
+    /// is retried on the first `get` after [`RETRY_DELAY`].
+    ///
+    /// Only one task refreshes at a time. The returned snapshot can be held
+    /// across awaits without holding up the next refresh.
+    pub async fn get(&self) -> Arc<D> {
+        if self.shared.is_expired() {
+            if let Ok(mut validators) = Arc::clone(&self.shared.validators).try_lock_owned() {
+                let shared = Arc::clone(&self.shared);
+                tokio::spawn(async move {
+                    // Another task may have finished a refresh between the
+                    // check and taking the lock.
+                    if shared.is_expired() {
+                        shared.refresh(&mut validators).await;
+                    }
+                });
+            }
+        }
+        self.shared.current()
+    }
+}
+
+impl<D> Shared<D> {
+    fn current(&self) -> Arc<D> {
+        Arc::clone(&self.data.read().expect("fresh data lock poisoned"))
+    }
+
+    fn is_expired(&self) -> bool {
+        Utc::now().timestamp_millis() >= self.expires.load(Ordering::Acquire)
+    }
+
+    fn mark_success(&self) {
+        let now = Utc::now();
+        self.last_success
+            .store(now.timestamp_millis(), Ordering::Release);
+        self.expires
+            .store((now + self.frequency).timestamp_millis(), Ordering::Release);
+    }
+
+    async fn refresh(&self, validators: &mut Validators) {
+        tracing::info!("Refreshing data!");
+        match (self.refresh)(validators.clone()).await {
+            Ok(Refresh::Updated(data, new_validators)) => {
+                // Let go of the lock before the old data is dropped, which can
+                // take a while for a large index.
+                let previous = std::mem::replace(
+                    &mut *self.data.write().expect("fresh data lock poisoned"),
+                    Arc::new(data),
+                );
+                drop(previous);
+                *validators = new_validators;
+                self.generation.fetch_add(1, Ordering::Release);
+                self.mark_success();
+            }
+            Ok(Refresh::NotModified) => {
+                tracing::info!("Data is unchanged");
+                self.mark_success();
+            }
+            Err(e) => {
+                tracing::warn!("Refresh failed, serving stale data: {e:#}");
+                self.expires.store(
+                    (Utc::now() + RETRY_DELAY).timestamp_millis(),
+                    Ordering::Release,
+                );
+            }
+        }
+    }
+}
+
+/// Fetch and decode JSON from `url`, as a conditional request when there are
+/// validators from a previous fetch.
+pub async fn fetch_json<D: DeserializeOwned>(
+    url: &str,
+    validators: Validators,
+) -> Result<Refresh<D>, anyhow::Error> {
+    let mut request = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()?
+        .get(url);
+    if let Some(etag) = &validators.etag {
+        request = request.header(header::IF_NONE_MATCH, etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+    }
+
+    let response = request.send().await?;
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Refresh::NotModified);
+    }
+    let response = response.error_for_status()?;
+
+    let header = |name| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let validators = Validators {
+        etag: header(header::ETAG),
+        last_modified: header(header::LAST_MODIFIED),
+    };
+    Ok(Refresh::Updated(response.json().await?, validators))
+}
+
+/// Read and decode a local JSON file, reporting it unchanged while its
+/// modification time stays the same. A missing file reads as `D::default()`,
+/// for optional files.
+pub async fn read_json_file<D: DeserializeOwned + Default>(
+    path: &str,
+    validators: Validators,
+) -> Result<Refresh<D>, anyhow::Error> {
+    let modified = match tokio::fs::metadata(path).await {
+        Ok(metadata) => metadata.modified().ok().map(|time| format!("{time:?}")),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Refresh::Updated(D::default(), Validators::default()));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if modified.is_some() && modified == validators.last_modified {
+        return Ok(Refresh::NotModified);
+    }
+
+    let text = tokio::fs::read_to_string(path).await?;
+    let validators = Validators {
+        etag: None,
+        last_modified: modified,
+    };
+    Ok(Refresh::Updated(serde_json::from_str(&text)?, validators))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    fn expire<D>(data: &FreshData<D>) {
+        data.shared.expires.store(0, Ordering::Release);
+    }
+
+    #[tokio::test]
+    async fn serves_stale_data_until_a_refresh_succeeds() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let refresh: RefreshFn<u32> = |_| {
+            Box::pin(async {
+                match CALLS.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(anyhow!("network blip")),
+                    _ => Ok(Refresh::Updated(1, Validators::default())),
+                }
+            })
+        };
+        let data = FreshData::stale(chrono::Duration::minutes(15), refresh, 0);
+        assert_eq!(*data.refreshed().await, 0);
+        assert_eq!(data.last_success(), None);
+        assert_eq!(data.generation(), 0);
+
+        // A failed refresh isn't retried straight away.
+        assert_eq!(*data.refreshed().await, 0);
+        assert_eq!(*data.get().await, 0);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+
+        expire(&data);
+        assert_eq!(*data.refreshed().await, 1);
+        assert!(data.last_success().is_some());
+        assert_eq!(data.generation(), 1);
+        assert!(!data.shared.is_expired());
+    }
+
+    #[tokio::test]
+    async fn not_modified_keeps_the_data() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let refresh: RefreshFn<u32> = |validators| {
+            Box::pin(async move {
+                match CALLS.fetch_add(1, Ordering::SeqCst) {
+                    0 => Ok(Refresh::Updated(
+                        1,
+                        Validators {
+                            etag: Some("\"v1\"".to_string()),
+                            last_modified: None,
+                        },
+                    )),
+                    _ => {
+                        assert_eq!(validators.etag.as_deref(), Some("\"v1\""));
+                        Ok(Refresh::NotModified)
+                    }
+                }
+            })
+        };
+        let data = FreshData::new(chrono::Duration::minutes(15), refresh)
+            .await
+            .unwrap();
+        expire(&data);
+        assert_eq!(*data.refreshed().await, 1);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        assert_eq!(data.generation(), 0);
+        assert!(data.last_success().is_some());
+        assert!(!data.shared.is_expired());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn only_one_task_refreshes_expired_data() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let refresh: RefreshFn<usize> = |_| {
+            Box::pin(async {
+                let call = CALLS.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Ok(Refresh::Updated(call, Validators::default()))
+            })
+        };
+        let data = Arc::new(
+            FreshData::new(chrono::Duration::minutes(15), refresh)
+                .await
+                .unwrap(),
+        );
+        // A snapshot held across the refresh doesn't hold it up.
+        let held = data.get().await;
+        expire(&data);
+        let tasks: Vec<_> = (0..64)
+            .map(|_| {
+                let data = Arc::clone(&data);
+                tokio::spawn(async move { *data.get().await })
+            })
+            .collect();
+        for task in tasks {
+            assert!(task.await.unwrap() <= 1);
+        }
+        // Waits for the refresh the first `get` started instead of running
+        // another.
+        assert_eq!(*data.refreshed().await, 1);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        assert_eq!(*held, 0);
+        assert_eq!(*data.get().await, 1);
+        assert_eq!(data.generation(), 1);
+    }
+
+    #[tokio::test]
+    async fn get_refreshes_in_the_background() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let refresh: RefreshFn<usize> = |_| {
+            Box::pin(async {
+                let call = CALLS.fetch_add(1, Ordering::SeqCst);
+                if call > 0 {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+                Ok(Refresh::Updated(call, Validators::default()))
+            })
+        };
+        let data = FreshData::new(chrono::Duration::minutes(15), refresh)
+            .await
+            .unwrap();
+        expire(&data);
+        let start = std::time::Instant::now();
+        assert_eq!(*data.get().await, 0);
+        assert_eq!(*data.get().await, 0);
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        assert_eq!(*data.refreshed().await, 1);
+        assert_eq!(CALLS.load(Ordering::SeqCst), 2);
+        assert_eq!(*data.get().await, 1);
+    }
+
+    #[tokio::test]
+    async fn not_modified_on_the_first_fetch_is_an_error() {
+        let refresh: RefreshFn<u32> = |_| Box::pin(async { Ok(Refresh::NotModified) });
+        assert!(FreshData::new(chrono::Duration::minutes(15), refresh)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn retries_the_first_load() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let refresh: RefreshFn<u32> = |_| {
+            Box::pin(async {
+                match CALLS.fetch_add(1, Ordering::SeqCst) {
+                    0 | 1 => Err(anyhow!("still starting up")),
+                    _ => Ok(Refresh::Updated(3, Validators::default())),
+                }
+            })
+        };
+        let data = FreshData::with_retries(
+            chrono::Duration::minutes(15),
+            refresh,
+            3,
+            Duration::from_millis(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 3);
+        assert_eq!(*data.get().await, 3);
+        assert!(data.last_success().is_some());
+
+        let refresh: RefreshFn<u32> = |_| Box::pin(async { Err(anyhow!("down")) });
+        let err = FreshData::with_retries(
+            chrono::Duration::minutes(15),
+            refresh,
+            2,
+            Duration::from_millis(1),
+        )
+        .await
+        .err()
+        .unwrap();
+        assert_eq!(err.to_string(), "Initial load failed 2 times");
+    }
+}
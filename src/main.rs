@@ -1,26 +1,45 @@
 #![deny(unused)]
 
 use std::{
-    collections::{HashMap, HashSet},
-    future::Future,
-    pin::Pin,
-    sync::OnceLock,
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap, HashSet},
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, Instant},
 };
 
+use anyhow::{anyhow, Context as _};
 use chrono::Utc;
-use duelingbook::DuelingBookCard;
+use deck::{AllowedCustom, Deck, DeckCard};
+use fresh::{fetch_json, read_json_file, FreshData, RefreshFn};
+use normalize::normalize_search_term;
 use poise::{
     serenity_prelude::{self as serenity, CreateAllowedMentions, CreateMessage},
-    CreateReply,
+    ChoiceParameter as _, CreateReply,
 };
+use rand::seq::SliceRandom as _;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tokio::sync::{RwLock, RwLockReadGuard};
+use terms::SearchTerms;
 use tracing_subscriber::{layer::SubscriberExt as _, Layer as _, Registry};
 
+mod deck;
 mod duelingbook;
+mod fresh;
+mod normalize;
+mod query;
+mod terms;
+mod ydk;
+mod ydke;
 
 const CARD_DATA: &str = "https://theplunderpirates.cc/card_data.json";
+/// Where the banlist is fetched from unless `BANLIST_URL` is set.
+const BANLIST_DATA: &str = "https://theplunderpirates.cc/banlist.json";
+/// Where the allowed customs are fetched from unless `ALLOWED_CUSTOMS_URL` is set.
+const ALLOWED_CUSTOMS_DATA: &str = "https://theplunderpirates.cc/allowed_customs.json";
+const SITE_URL: &str = "https://theplunderpirates.cc";
 const IMG_BASE: &str = "https://theplunderpirates.cc/card_images/";
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -42,42 +61,456 @@ struct CardDatum {
     number_value: Option<u32>,
     level: Option<u32>,
     linkval: Option<u32>,
+    /// Other names the card is always treated as, such as the original of a
+    /// retrain. Searches by name find the card under these too.
+    #[serde(default, alias = "treated_as")]
+    aliases: Option<Vec<String>>,
+    /// Keys in the card data that none of the fields above read, kept so
+    /// schema changes can be noticed, see [`warn_unknown_keys`].
+    #[serde(flatten)]
+    extra: HashMap<String, serde_json::Value>,
 }
 
-struct FreshData<D> {
-    frequency: chrono::Duration,
-    refresh: fn() -> Pin<Box<dyn Future<Output = D> + Send>>,
-    data: RwLock<(chrono::DateTime<Utc>, D)>,
+/// Unknown card data keys that have already been warned about.
+static REPORTED_KEYS: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+
+/// Warn about card data keys that `CardDatum` doesn't know, since whatever
+/// they hold is being dropped, skipping keys already in `reported`. Returns
+/// the keys warned about, which are added to `reported`.
+fn warn_unknown_keys(cards: &[CardDatum], reported: &mut BTreeSet<String>) -> Vec<String> {
+    let new: BTreeSet<&str> = cards
+        .iter()
+        .flat_map(|card| card.extra.keys())
+        .map(String::as_str)
+        .filter(|key| !reported.contains(*key))
+        .collect();
+    for &key in &new {
+        let example = cards
+            .iter()
+            .find(|card| card.extra.contains_key(key))
+            .map(|card| card.name.as_str())
+            .unwrap_or_default();
+        tracing::warn!("Card data has an unknown key `{key}`, first seen on {example:?}");
+        reported.insert(key.to_string());
+    }
+    new.into_iter().map(str::to_string).collect()
+}
+
+/// The card pool along with the search-normalized name and description of
+/// each card, computed once per refresh rather than on every query.
+struct CardIndex {
+    cards: Vec<CardDatum>,
+    norm_names: Vec<String>,
+    norm_descs: Vec<String>,
+    norm_aliases: Vec<Vec<String>>,
+    /// The cards whose normalized name contains each trigram, see
+    /// [`name_trigrams`].
+    trigrams: HashMap<[char; 3], Vec<usize>>,
+    /// How many distinct trigrams each card's name has.
+    trigram_counts: Vec<usize>,
+}
+
+/// The distinct character trigrams of a normalized name, with its spaces
+/// dropped so that "darkmagician" still lines up with "dark magician", and
+/// padded so the first and last letters count as much as the rest.
+fn name_trigrams(name: &str) -> BTreeSet<[char; 3]> {
+    let chars: Vec<char> = std::iter::once(' ')
+        .chain(name.chars().filter(|c| !c.is_whitespace()))
+        .chain(std::iter::once(' '))
+        .collect();
+    chars.windows(3).map(|w| [w[0], w[1], w[2]]).collect()
 }
 
-impl<D> FreshData<D> {
-    async fn new(
-        frequency: chrono::Duration,
-        refresh: fn() -> Pin<Box<dyn Future<Output = D> + Send>>,
-    ) -> FreshData<D> {
-        let data = (refresh)().await;
-        FreshData {
-            frequency,
-            refresh,
-            data: RwLock::new((Utc::now() + frequency, data)),
+impl CardIndex {
+    fn new(cards: Vec<CardDatum>) -> CardIndex {
+        // Once per key for the life of the bot, not on every refresh.
+        warn_unknown_keys(
+            &cards,
+            &mut REPORTED_KEYS.lock().expect("reported keys poisoned"),
+        );
+        let norm_names: Vec<String> = cards
+            .iter()
+            .map(|c| normalize_search_term(&c.name))
+            .collect();
+        let norm_descs = cards
+            .iter()
+            .map(|c| normalize_search_term(&c.desc))
+            .collect();
+        let norm_aliases = cards
+            .iter()
+            .map(|c| {
+                c.aliases()
+                    .iter()
+                    .map(|a| normalize_search_term(a))
+                    .collect()
+            })
+            .collect();
+        let mut trigrams: HashMap<[char; 3], Vec<usize>> = HashMap::new();
+        let mut trigram_counts = Vec::new();
+        for (i, name) in norm_names.iter().enumerate() {
+            let grams = name_trigrams(name);
+            trigram_counts.push(grams.len());
+            for gram in grams {
+                trigrams.entry(gram).or_default().push(i);
+            }
+        }
+        CardIndex {
+            cards,
+            norm_names,
+            norm_descs,
+            norm_aliases,
+            trigrams,
+            trigram_counts,
         }
     }
 
-    async fn get(&self) -> RwLockReadGuard<'_, D> {
-        {
-            let mut lock = self.data.write().await;
-            if Utc::now() >= lock.0 {
-                tracing::info!("Refreshing data!");
-                lock.1 = (self.refresh)().await;
-                lock.0 = Utc::now() + self.frequency;
+    fn iter(&self) -> std::slice::Iter<'_, CardDatum> {
+        self.cards.iter()
+    }
+
+    /// Passcodes (`number_value`) keyed by card name.
+    fn name_passcodes(&self) -> HashMap<&str, u32> {
+        self.cards
+            .iter()
+            .filter_map(|c| Some((c.name.as_str(), c.number_value?)))
+            .collect()
+    }
+
+    /// Card names keyed by passcode (`number_value`).
+    fn passcode_names(&self) -> HashMap<u32, &str> {
+        self.cards
+            .iter()
+            .filter_map(|c| Some((c.number_value?, c.name.as_str())))
+            .collect()
+    }
+
+    /// Each card with its normalized name and description.
+    fn entries(&self) -> impl Iterator<Item = (&CardDatum, &str, &str)> {
+        self.cards
+            .iter()
+            .zip(&self.norm_names)
+            .zip(&self.norm_descs)
+            .map(|((card, name), desc)| (card, name.as_str(), desc.as_str()))
+    }
+
+    /// Like `entries`, with the normalized aliases of each card as well.
+    fn entries_with_aliases(&self) -> impl Iterator<Item = (&CardDatum, &str, &str, &[String])> {
+        self.entries()
+            .zip(&self.norm_aliases)
+            .map(|((card, name, desc), aliases)| (card, name, desc, aliases.as_slice()))
+    }
+
+    /// Cards whose normalized name is within a small edit distance of the
+    /// normalized term, closest first.
+    ///
+    /// The name is compared both whole and cut to the term's length, so a
+    /// typo in the start of a longer name ("plunder patrol") still finds it.
+    /// The allowed distance grows with the term, and terms under four
+    /// characters get no suggestions, since nearly anything is close to them.
+    fn closest(&self, term: &str) -> Vec<&CardDatum> {
+        let term = normalize_search_term(term);
+        let term = term.trim();
+        let term_len = term.chars().count();
+        if term_len < 4 {
+            return Vec::new();
+        }
+        let max_distance = (term_len / 4).clamp(1, 3);
+
+        let mut candidates: Vec<_> = self
+            .entries()
+            .map(|(card, name, _)| {
+                let prefix: String = name.chars().take(term_len).collect();
+                let distance = levenshtein::levenshtein(term, name)
+                    .min(levenshtein::levenshtein(term, &prefix));
+                (distance, card)
+            })
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+        candidates.sort_by(|(a, a_card), (b, b_card)| a.cmp(b).then(a_card.name.cmp(&b_card.name)));
+        candidates
+            .into_iter()
+            .take(5)
+            .map(|(_, card)| card)
+            .collect()
+    }
+
+    /// Up to `limit` cards whose names share the most trigrams with `term`,
+    /// for suggestions when nothing matches it. At least half of the term's
+    /// trigrams have to appear in a name, so typos, transposed letters,
+    /// missing spaces and the start of a word all find it, and ties go to
+    /// the names closest in length. Only cards that pass `keep` are
+    /// suggested, so a suggestion never fails the search's other filters.
+    fn suggest(
+        &self,
+        term: &str,
+        limit: usize,
+        keep: impl Fn(&CardDatum) -> bool,
+    ) -> Vec<&CardDatum> {
+        let term = normalize_search_term(term);
+        if term.chars().filter(|c| !c.is_whitespace()).count() < 3 {
+            return Vec::new();
+        }
+        let grams = name_trigrams(&term);
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for gram in &grams {
+            for &i in self.trigrams.get(gram).into_iter().flatten() {
+                *shared.entry(i).or_default() += 1;
+            }
+        }
+        let mut candidates: Vec<_> = shared
+            .into_iter()
+            .filter(|&(i, count)| count * 2 >= grams.len() && keep(&self.cards[i]))
+            .collect();
+        candidates.sort_by_key(|&(i, count)| {
+            (
+                std::cmp::Reverse(count),
+                self.trigram_counts[i],
+                &self.cards[i].name,
+            )
+        });
+        candidates
+            .into_iter()
+            .take(limit)
+            .map(|(i, _)| &self.cards[i])
+            .collect()
+    }
+
+    /// The cards whose name and description match the search terms and which
+    /// pass `filter`.
+    fn matching<'a>(
+        &'a self,
+        name: &'a SearchTerms,
+        effect: &'a SearchTerms,
+        filter: &'a CardFilter,
+    ) -> impl Iterator<Item = &'a CardDatum> {
+        self.matching_entries(name, effect, filter)
+            .map(|(card, _, _)| card)
+    }
+
+    /// Like `matching`, along with each card's normalized name and aliases,
+    /// for ranking the matches without normalizing their names again.
+    fn matching_entries<'a>(
+        &'a self,
+        name: &'a SearchTerms,
+        effect: &'a SearchTerms,
+        filter: &'a CardFilter,
+    ) -> impl Iterator<Item = (&'a CardDatum, &'a str, &'a [String])> {
+        self.entries_with_aliases()
+            .filter(|(card, card_name, _, aliases)| {
+                name.matches(&card.name, card_name)
+                    || card
+                        .aliases()
+                        .iter()
+                        .zip(*aliases)
+                        .any(|(raw, normalized)| name.matches(raw, normalized))
+            })
+            .filter(|(card, _, card_desc, _)| effect.matches(&card.desc, card_desc))
+            .filter(|(card, _, _, _)| filter.apply(card))
+            .map(|(card, name, _, aliases)| (card, name, aliases))
+    }
+
+    /// The cards matching a search, in `sort` order. `name_terms` and
+    /// `effect_terms` are the parsed `card_name` and effect queries.
+    fn search<'a>(
+        &'a self,
+        card_name: Option<&str>,
+        name_terms: &'a SearchTerms,
+        effect_terms: &'a SearchTerms,
+        filter: &'a CardFilter,
+        sort: SortOrder,
+    ) -> Vec<&'a CardDatum> {
+        // Work with references until the listing is cut down, so broad
+        // searches don't clone most of the pool.
+        let mut matches: Vec<_> = self
+            .matching_entries(name_terms, effect_terms, filter)
+            .collect();
+        // A pattern isn't a name, so no card can be named exactly like it.
+        if let Some(name) = card_name.filter(|_| filter.match_mode != MatchMode::Regex) {
+            // A card named exactly like the query wins over the cards whose
+            // names merely contain it. Aliases only count when no card has
+            // the query as its real name.
+            let query = normalize_search_term(name.trim());
+            let by_alias = !matches.iter().any(|(_, name, _)| *name == query);
+            let is_exact = |(_, name, aliases): &(&CardDatum, &str, &[String])| {
+                if by_alias {
+                    aliases.contains(&query)
+                } else {
+                    *name == query
+                }
+            };
+            if filter.exact_name || matches.iter().filter(|m| is_exact(m)).count() == 1 {
+                matches.retain(|m| is_exact(m));
+            }
+        }
+        if sort == SortOrder::Relevance {
+            let query = name_terms.first_positive().unwrap_or_default();
+            matches.sort_by_cached_key(|&(card, name, _)| {
+                (relevance(query, name), card.name.as_str())
+            });
+        }
+        let mut cards: Vec<&CardDatum> = matches.into_iter().map(|(card, _, _)| card).collect();
+        match sort {
+            SortOrder::Name => cards.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortOrder::PasscodeAscending => {
+                sort_missing_last(&mut cards, |c| c.number_value, false)
+            }
+            SortOrder::PasscodeDescending => {
+                sort_missing_last(&mut cards, |c| c.number_value, true)
+            }
+            SortOrder::Level => sort_missing_last(&mut cards, |c| c.level.or(c.linkval), true),
+            SortOrder::Atk => sort_missing_last(&mut cards, |c| c.atk, true),
+            SortOrder::Def => sort_missing_last(&mut cards, |c| c.def, true),
+            SortOrder::Type => cards.sort_by(|a, b| {
+                (&a.ty, &a.full_type, &a.name).cmp(&(&b.ty, &b.full_type, &b.name))
+            }),
+            // Already ranked above, while the normalized names were at hand.
+            SortOrder::Relevance => {}
+        }
+        cards
+    }
+}
+
+/// Recent name autocomplete results keyed by the normalized partial and the
+/// effect filter, so the prefixes typed on the way to a card name don't each
+/// rescan the pool.
+struct AutocompleteCache {
+    ttl: Duration,
+    capacity: usize,
+    generation: u64,
+    entries: HashMap<String, (Instant, Vec<String>)>,
+}
+
+impl AutocompleteCache {
+    fn new(ttl: Duration, capacity: usize) -> AutocompleteCache {
+        AutocompleteCache {
+            ttl,
+            capacity,
+            generation: 0,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Drop everything if the card data has been refreshed since the cache
+    /// was filled.
+    fn sync_generation(&mut self, generation: u64) {
+        if self.generation != generation {
+            self.entries.clear();
+            self.generation = generation;
+        }
+    }
+
+    fn get(&mut self, generation: u64, partial: &str) -> Option<Vec<String>> {
+        self.sync_generation(generation);
+        match self.entries.get(partial) {
+            Some((at, names)) if at.elapsed() < self.ttl => Some(names.clone()),
+            _ => None,
+        }
+    }
+
+    fn insert(&mut self, generation: u64, partial: String, names: Vec<String>) {
+        self.sync_generation(generation);
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity {
+            let ttl = self.ttl;
+            self.entries.retain(|_, (at, _)| at.elapsed() < ttl);
+        }
+        if self.entries.len() >= self.capacity {
+            if let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (at, _))| *at)
+                .map(|(k, _)| k.clone())
+            {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(partial, (Instant::now(), names));
+    }
+}
+
+/// Card name to the number of copies allowed in a deck. Cards that aren't
+/// listed are unrestricted.
+type Banlist = HashMap<String, u8>;
+
+/// Cards in a deck over their banlist limit, split into forbidden cards and
+/// limited cards with too many copies.
+struct BanlistViolations<'a> {
+    forbidden: Vec<(&'a str, usize)>,
+    over_limit: Vec<(&'a str, usize, u8)>,
+}
+
+impl<'a> BanlistViolations<'a> {
+    /// Copies are counted across every zone, so an Extra Deck monster is
+    /// tallied from the Extra Deck alongside the Main and Side.
+    fn new(banlist: &Banlist, deck: &'a Deck) -> BanlistViolations<'a> {
+        let mut copies: HashMap<&str, usize> = HashMap::new();
+        for card in deck.main.iter().chain(&deck.extra).chain(&deck.side) {
+            *copies.entry(&card.name).or_default() += 1;
+        }
+        let mut forbidden = vec![];
+        let mut over_limit = vec![];
+        for (name, count) in copies {
+            match banlist.get(name) {
+                Some(0) => forbidden.push((name, count)),
+                Some(&limit) if count > limit as usize => over_limit.push((name, count, limit)),
+                _ => {}
+            }
+        }
+        forbidden.sort();
+        over_limit.sort();
+        BanlistViolations {
+            forbidden,
+            over_limit,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Submission {
+    user: serenity::UserId,
+    at: chrono::DateTime<Utc>,
+}
+
+/// The first submission of each DuelingBook deck id seen by `check_deck`,
+/// used to flag decks that are submitted more than once.
+#[derive(Debug, Default)]
+struct SubmissionLog {
+    first: HashMap<u32, Submission>,
+}
+
+impl SubmissionLog {
+    /// Record a submission, returning the earlier one if this deck has been
+    /// submitted before.
+    fn record(&mut self, deck_id: u32, submission: Submission) -> Option<Submission> {
+        match self.first.entry(deck_id) {
+            Entry::Occupied(first) => Some(*first.get()),
+            Entry::Vacant(entry) => {
+                entry.insert(submission);
+                None
             }
         }
-        RwLockReadGuard::map(self.data.read().await, |(_, d)| d)
     }
 }
 
 struct Data {
-    cards: FreshData<Vec<CardDatum>>,
+    cards: FreshData<CardIndex>,
+    banlist: FreshData<Banlist>,
+    /// Custom cards that are legal despite not being in `cards`.
+    allowed_customs: FreshData<Vec<AllowedCustom>>,
+    /// Operator notes on cards keyed by card name, from `ANNOTATIONS_FILE`.
+    annotations: FreshData<HashMap<String, String>>,
+    autocomplete_cache: Mutex<AutocompleteCache>,
+    /// Only tracked when `TRACK_DECK_SUBMISSIONS` is enabled.
+    submissions: Option<Mutex<SubmissionLog>>,
+    /// Bounds how many image URLs are checked at once, set by
+    /// `IMAGE_CHECK_CONCURRENCY`.
+    image_checks: tokio::sync::Semaphore,
+    /// Whether each art URL answered a HEAD request, and when it was checked.
+    art_reachability: Mutex<HashMap<String, (Instant, bool)>>,
+    /// When the bot finished starting up, for `/status`.
+    started: chrono::DateTime<Utc>,
 }
 
 static DISALLOWED_CHARACTERS: OnceLock<Regex> = OnceLock::new();
@@ -92,21 +525,119 @@ fn ws() -> &'static Regex {
     WHITESPACE.get_or_init(|| Regex::new(r"\s+").expect("Cannot compile whitespace re"))
 }
 
+/// Negative ATK/DEF values stand for "?" in the card data.
+fn is_unknown_stat(stat: i32) -> bool {
+    stat < 0
+}
+
+fn display_stat(stat: i32) -> String {
+    if is_unknown_stat(stat) {
+        "?".to_string()
+    } else {
+        stat.to_string()
+    }
+}
+
+/// Describe how long ago `as_of` was, e.g. "5 minutes ago".
+fn format_age(as_of: chrono::DateTime<Utc>) -> String {
+    match format_duration(Utc::now() - as_of) {
+        Some(age) => format!("{age} ago"),
+        None => "just now".to_string(),
+    }
+}
+
+/// Describe `duration` in its largest whole unit, e.g. "3 hours", or `None`
+/// if it is under a minute.
+fn format_duration(duration: chrono::Duration) -> Option<String> {
+    let (count, unit) = if duration.num_days() > 0 {
+        (duration.num_days(), "day")
+    } else if duration.num_hours() > 0 {
+        (duration.num_hours(), "hour")
+    } else if duration.num_minutes() > 0 {
+        (duration.num_minutes(), "minute")
+    } else {
+        return None;
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    Some(format!("{count} {unit}{plural}"))
+}
+
+/// The URL-ready name of the file the TPP site stores a card's art in:
+///
+/// - each of `?/'!,:&."` becomes `_`, so "Ash-Blossom & Joyous Spring"
+///   is stored as "Ash-Blossom _ Joyous Spring.jpg"
+/// - each run of whitespace becomes a single space
+/// - hyphens, underscores, tildes, and ASCII letters and digits are kept as
+///   they are
+/// - everything else, including the spaces, `#`, and accented letters, is
+///   percent-encoded as UTF-8, so that `#` can't start a URL fragment
+fn image_filename(name: &str) -> String {
+    let removed_disallowed = disallowed_characters().replace_all(name, "_");
+    let spaced = ws().replace_all(&removed_disallowed, " ");
+    let mut filename = String::new();
+    for byte in spaced.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'~') {
+            filename.push(char::from(byte));
+        } else {
+            filename.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    filename.push_str(".jpg");
+    filename
+}
+
+/// Where the TPP site hosts the art for the card called `name`.
+fn image_url_for(name: &str) -> String {
+    format!("{IMG_BASE}{}", image_filename(name))
+}
+
+/// Type lines longer than this wrap awkwardly in an inline field on desktop
+/// clients, squeezing the neighbouring fields, so they get a row to themselves.
+const INLINE_TYPE_LIMIT: usize = 32;
+
 impl CardDatum {
-    fn make_embed(&self) -> serenity::CreateEmbed {
-        let removed_disallowed = disallowed_characters().replace_all(&self.name, "_");
-        let formatted_name = ws().replace_all(&removed_disallowed, "%20");
-        let img_url = format!("{IMG_BASE}{formatted_name}.jpg");
+    /// `as_of` is when the card data was last refreshed, shown in the footer,
+    /// and `note` is the card's entry in the annotations, if any.
+    fn make_embed(
+        &self,
+        as_of: chrono::DateTime<Utc>,
+        note: Option<&str>,
+    ) -> serenity::CreateEmbed {
+        self.build_embed(false, as_of, note)
+    }
+
+    /// Like `make_embed`, but with the description and art hidden behind
+    /// Discord spoiler markup for controlled reveals.
+    fn make_spoiler_embed(
+        &self,
+        as_of: chrono::DateTime<Utc>,
+        note: Option<&str>,
+    ) -> serenity::CreateEmbed {
+        self.build_embed(true, as_of, note)
+    }
+
+    fn build_embed(
+        &self,
+        spoiler: bool,
+        as_of: chrono::DateTime<Utc>,
+        note: Option<&str>,
+    ) -> serenity::CreateEmbed {
         let mut embed = serenity::CreateEmbed::new()
             .title(self.name.clone())
-            .description(self.desc.clone())
+            .description(if spoiler {
+                format!("||{}||", self.desc)
+            } else {
+                self.desc.clone()
+            })
             .footer(
-                serenity::CreateEmbedFooter::new("The Plunder Pirates")
-                    .icon_url("https://theplunderpirates.cc/icon/apple-touch-icon.png"),
+                serenity::CreateEmbedFooter::new(format!(
+                    "The Plunder Pirates • Data as of {}",
+                    format_age(as_of)
+                ))
+                .icon_url("https://theplunderpirates.cc/icon/apple-touch-icon.png"),
             )
             .url({
-                let mut url =
-                    reqwest::Url::parse("https://theplunderpirates.cc").expect("must be valid");
+                let mut url = reqwest::Url::parse(SITE_URL).expect("must be valid");
                 url.set_query(Some(&format!("current_card={}", self.name)));
                 url.to_string()
             })
@@ -121,9 +652,20 @@ impl CardDatum {
 
                 _ => serenity::Color::DARK_GREY,
             })
-            .image(img_url)
-            .field("Type", self.full_type.clone(), true)
+            .field(
+                "Type",
+                self.full_type.clone(),
+                self.full_type.chars().count() <= INLINE_TYPE_LIMIT,
+            )
             .field(self.ty.replace("self", "Type"), self.race.clone(), true);
+        // Embed images cannot be spoilered, so link the art instead.
+        match self.art_url() {
+            Some(img_url) if spoiler => {
+                embed = embed.field("Art", format!("||[View art]({img_url})||"), true);
+            }
+            Some(img_url) => embed = embed.image(img_url),
+            None => {}
+        }
         if let Some(attr) = &self.attribute {
             embed = embed.field("Attribute", attr.clone(), true);
         }
@@ -136,303 +678,4780 @@ impl CardDatum {
         if let Some((atk, def)) = self.atk.zip(self.def) {
             embed = embed.field(
                 "Atk/Def",
-                format!(
-                    "{} / {}",
-                    if atk < 0 {
-                        "?".to_string()
-                    } else {
-                        atk.to_string()
-                    },
-                    if def < 0 {
-                        "?".to_string()
-                    } else {
-                        def.to_string()
-                    }
-                ),
+                format!("{} / {}", display_stat(atk), display_stat(def)),
                 true,
             );
         }
+        if !self.aliases().is_empty() {
+            embed = embed.field("Also treated as", self.aliases().join(", "), false);
+        }
+        if let Some(note) = note {
+            embed = embed.field("Note", note, false);
+        }
         embed
     }
-}
 
-fn normalize_search_term(term: &str) -> String {
-    disallowed_characters()
-        .replace_all(term, "_")
-        .to_lowercase()
-}
+    fn aliases(&self) -> &[String] {
+        self.aliases.as_deref().unwrap_or_default()
+    }
 
-impl Data {
-    async fn filter_cards(&self, name: Option<&str>, effect: Option<&str>) -> Vec<CardDatum> {
-        let cards = self.cards.get().await;
-        let name = normalize_search_term(name.unwrap_or_default());
-        let effect = normalize_search_term(effect.unwrap_or_default());
+    /// The card's art: the URL from the card data if there is one, otherwise
+    /// the one the TPP site would use for its name.
+    fn art_url(&self) -> Option<String> {
+        self.art_candidates().into_iter().next()
+    }
 
-        cards
-            .iter()
-            .filter(|card| {
-                let card_name = normalize_search_term(&card.name);
-                for term in name.split("*") {
-                    if !card_name.contains(term.trim()) {
-                        return false;
-                    }
-                }
-                true
-            })
-            .filter(|card| {
-                let card_desc = normalize_search_term(&card.desc);
-                for term in effect.split("*") {
-                    if !card_desc.contains(term.trim()) {
-                        return false;
-                    }
-                }
-                true
-            })
-            .cloned()
-            .collect()
+    /// Every URL the card's art might be at, in order of preference.
+    fn art_candidates(&self) -> Vec<String> {
+        let mut candidates = Vec::new();
+        if !self.image_url.trim().is_empty() {
+            candidates.push(self.image_url.clone());
+        }
+        if !self.name.trim().is_empty() {
+            let constructed = image_url_for(&self.name);
+            if !candidates.contains(&constructed) {
+                candidates.push(constructed);
+            }
+        }
+        candidates
     }
 
-    async fn get_reply(
-        &self,
-        card_name: Option<&str>,
-        effect: Option<&str>,
-    ) -> Result<serenity::CreateEmbed, anyhow::Error> {
-        let mut cards = self.filter_cards(card_name, effect).await;
-        cards.sort_by(|a, b| a.name.cmp(&b.name));
+    /// A single line for the "Did you mean" listing.
+    fn list_entry(&self) -> String {
+        match (self.level, self.linkval) {
+            (Some(level), _) => format!("{} (Level {level})", self.name),
+            (None, Some(linkval)) => format!("{} (Link-{linkval})", self.name),
+            (None, None) => self.name.clone(),
+        }
+    }
 
-        Ok(match cards.len() {
-            0 => serenity::CreateEmbed::new()
-                .title("No cards found".to_string())
-                .description("No cards were found that match the provided filters"),
-            1 => cards[0].make_embed(),
-            _ => serenity::CreateEmbed::new()
-                .title("Multiple matches found".to_string())
-                .description(format!(
-                    "Did you mean: \n- {}",
-                    cards
-                        .into_iter()
-                        .take(25)
-                        .map(|card| card.name.to_string())
-                        .collect::<Vec<_>>()
-                        .join("\n- ")
-                )),
-        })
+    /// A line of the `/archetype` listing: monsters with their level and
+    /// stats, spells and traps with their kind.
+    fn archetype_entry(&self) -> String {
+        if CardType::Spell.matches(self) || CardType::Trap.matches(self) {
+            return format!("{} ({})", self.name, self.race);
+        }
+        let mut details = Vec::new();
+        match (self.level, self.linkval) {
+            (_, Some(linkval)) => details.push(format!("Link-{linkval}")),
+            (Some(level), None) if self.frame_type.starts_with("xyz") => {
+                details.push(format!("Rank {level}"))
+            }
+            (Some(level), None) => details.push(format!("Level {level}")),
+            (None, None) => {}
+        }
+        match (self.atk, self.def) {
+            (Some(atk), Some(def)) => {
+                details.push(format!("{}/{}", display_stat(atk), display_stat(def)))
+            }
+            (Some(atk), None) => details.push(format!("ATK {}", display_stat(atk))),
+            _ => {}
+        }
+        if details.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} ({})", self.name, details.join(", "))
+        }
+    }
+
+    /// A single row of the `/search list` table.
+    fn listing_row(&self) -> String {
+        let rating = match (self.level, self.linkval) {
+            (_, Some(linkval)) => format!("Link-{linkval}"),
+            (Some(level), None) if self.frame_type.starts_with("xyz") => format!("Rank {level}"),
+            (Some(level), None) => format!("Lv {level}"),
+            (None, None) => String::new(),
+        };
+        let stats = match (self.atk, self.def) {
+            (Some(atk), Some(def)) => format!("{}/{}", display_stat(atk), display_stat(def)),
+            (Some(atk), None) => display_stat(atk),
+            _ => String::new(),
+        };
+        format!(
+            "{} {} {:<6} {:<7} {stats}",
+            fit(&self.name, LISTING_NAME_WIDTH),
+            fit(&self.race, LISTING_RACE_WIDTH),
+            self.attribute.as_deref().unwrap_or(""),
+            rating,
+        )
+        .trim_end()
+        .to_string()
+    }
+
+    /// The card's vital stats, one per line, for `/compare`.
+    fn stat_lines(&self) -> String {
+        let mut lines = vec![format!("**Type:** {}", self.full_type)];
+        lines.push(format!(
+            "**Attribute:** {}",
+            self.attribute.as_deref().unwrap_or("—")
+        ));
+        match (self.level, self.linkval) {
+            (Some(level), _) => lines.push(format!("**Level:** {level}")),
+            (None, Some(linkval)) => lines.push(format!("**Link Value:** {linkval}")),
+            (None, None) => lines.push("**Level:** —".to_string()),
+        }
+        lines.push(match self.atk.zip(self.def) {
+            Some((atk, def)) => {
+                format!("**Atk/Def:** {} / {}", display_stat(atk), display_stat(def))
+            }
+            None => match self.atk {
+                Some(atk) => format!("**Atk:** {}", display_stat(atk)),
+                None => "**Atk/Def:** —".to_string(),
+            },
+        });
+        lines.join("\n")
+    }
+
+    /// The card as plain message text rather than an embed, for `/text`:
+    /// the name, type line and stats, then the effect, with the card's own
+    /// text escaped so it shows up as written.
+    fn plain_text(&self, note: Option<&str>) -> String {
+        let mut stats = Vec::new();
+        if let Some(attr) = &self.attribute {
+            stats.push(escape_markdown(attr));
+        }
+        match (self.level, self.linkval) {
+            (_, Some(linkval)) => stats.push(format!("Link-{linkval}")),
+            (Some(level), None) if self.frame_type.starts_with("xyz") => {
+                stats.push(format!("Rank {level}"))
+            }
+            (Some(level), None) => stats.push(format!("Level {level}")),
+            (None, None) => {}
+        }
+        match (self.atk, self.def) {
+            (Some(atk), Some(def)) => stats.push(format!(
+                "ATK {} / DEF {}",
+                display_stat(atk),
+                display_stat(def)
+            )),
+            (Some(atk), None) => stats.push(format!("ATK {}", display_stat(atk))),
+            _ => {}
+        }
+
+        let mut lines = vec![
+            format!("**{}**", escape_markdown(&self.name)),
+            escape_markdown(&self.full_type),
+        ];
+        if !stats.is_empty() {
+            lines.push(stats.join(", "));
+        }
+        lines.push(String::new());
+        lines.push(escape_markdown(&self.desc));
+        if !self.aliases().is_empty() {
+            lines.push(String::new());
+            lines.push(format!(
+                "Also treated as: {}",
+                escape_markdown(&self.aliases().join(", "))
+            ));
+        }
+        if let Some(note) = note {
+            lines.push(String::new());
+            lines.push(format!("Note: {}", escape_markdown(note)));
+        }
+        lines.join("\n")
     }
 }
 
-type Context<'a> = poise::Context<'a, Data, anyhow::Error>;
+/// Backslash-escape whatever Discord would read as markdown in `text`: the
+/// inline markers anywhere, and quote, heading and list markers at the start
+/// of a line.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        escaped.push_str(&line[..line.len() - trimmed.len()]);
+        if trimmed.starts_with(['>', '#', '-']) {
+            escaped.push('\\');
+        }
+        for c in trimmed.chars() {
+            if matches!(c, '\\' | '*' | '_' | '~' | '`' | '|') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+    }
+    escaped
+}
 
-async fn event_handler(
-    ctx: &serenity::Context,
-    event: &serenity::FullEvent,
-    _framework: poise::FrameworkContext<'_, Data, anyhow::Error>,
-    data: &Data,
-) -> Result<(), anyhow::Error> {
-    if let serenity::FullEvent::Message { new_message } = event {
-        if let Some(msg) = new_message
-            .content
-            .strip_prefix("<")
-            .and_then(|msg| msg.strip_suffix(">"))
-        {
-            if !msg.starts_with("@") {
-                let builder = CreateMessage::new()
-                    .add_embed(data.get_reply(Some(msg), None).await?)
-                    .reference_message(new_message)
-                    .allowed_mentions(
-                        CreateAllowedMentions::new()
-                            .replied_user(false)
-                            .everyone(true)
-                            .all_users(true)
-                            .all_roles(true),
-                    );
-                new_message.channel_id.send_message(ctx, builder).await?;
+/// The longest message content Discord accepts.
+const MESSAGE_LIMIT: usize = 2000;
+
+/// Cut `text` into pieces Discord will accept as messages, at line breaks
+/// where possible.
+fn split_message(text: &str) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut piece_len = 0;
+    for line in text.split_inclusive('\n') {
+        let line_len = line.chars().count();
+        if piece_len + line_len > MESSAGE_LIMIT && piece_len > 0 {
+            pieces.push(std::mem::take(&mut piece));
+            piece_len = 0;
+        }
+        // A line too long for a message of its own is cut wherever it fills one.
+        for c in line.chars() {
+            if piece_len == MESSAGE_LIMIT {
+                pieces.push(std::mem::take(&mut piece));
+                piece_len = 0;
             }
+            piece.push(c);
+            piece_len += 1;
         }
     }
-    Ok(())
+    if !piece.trim().is_empty() {
+        pieces.push(piece);
+    }
+    pieces
 }
 
-async fn autocomplete_search(ctx: Context<'_>, partial: &str) -> Vec<String> {
-    let mut result = ctx
-        .data()
-        .cards
-        .get()
-        .await
-        .iter()
-        .filter(|c| c.name.to_lowercase().contains(&partial.to_lowercase()))
-        .map(|c| c.name.clone())
-        .collect::<Vec<_>>();
-    result.sort();
-    result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum CardType {
+    Monster,
+    Spell,
+    Trap,
 }
 
-#[poise::command(slash_command)]
-/// Search for a card in the TPP format. Separate search terms with *.
-async fn search(
-    ctx: Context<'_>,
-    #[description = "Card Name"]
-    #[autocomplete = autocomplete_search]
-    name: Option<String>,
-    #[description = "Card Effect"] effect: Option<String>,
-) -> Result<(), anyhow::Error> {
-    ctx.send(
-        CreateReply::default().embed(
-            ctx.data()
-                .get_reply(name.as_deref(), effect.as_deref())
-                .await?,
-        ),
-    )
-    .await?;
-    Ok(())
+impl CardType {
+    fn matches(self, card: &CardDatum) -> bool {
+        match self {
+            CardType::Spell => card.frame_type == "spell",
+            CardType::Trap => card.frame_type == "trap",
+            CardType::Monster => card.frame_type != "spell" && card.frame_type != "trap",
+        }
+    }
 }
 
-#[poise::command(slash_command)]
-/// Verify that the provided deck is valid in the TPP format.
-async fn check_deck(
-    ctx: Context<'_>,
-    #[description = "Deck URL in the format https://www.duelingbook.com/deck?id=<id>"] url: String,
-) -> Result<(), anyhow::Error> {
-    ctx.defer_ephemeral().await?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum Attribute {
+    #[name = "DARK"]
+    Dark,
+    #[name = "LIGHT"]
+    Light,
+    #[name = "WATER"]
+    Water,
+    #[name = "FIRE"]
+    Fire,
+    #[name = "EARTH"]
+    Earth,
+    #[name = "WIND"]
+    Wind,
+    #[name = "DIVINE"]
+    Divine,
+}
 
-    let data = ctx.data().cards.get().await;
-    let valid_cards: HashSet<_> = data.iter().map(|c| &c.name).cloned().collect();
-    let deck = duelingbook::DuelingBookDeck::get_deck(url).await?;
+impl Attribute {
+    fn matches(self, card: &CardDatum) -> bool {
+        card.attribute
+            .as_ref()
+            .is_some_and(|attr| attr.eq_ignore_ascii_case(self.name()))
+    }
+}
 
-    fn track_invalids<'a>(
-        valid_cards: &HashSet<String>,
-        cards: &'a Vec<DuelingBookCard>,
-    ) -> (HashMap<&'a DuelingBookCard, usize>, usize) {
-        let mut invalids = HashMap::new();
-        let mut invalid_count = 0;
-        for card in cards {
-            if !valid_cards.contains(&card.name) {
-                *invalids.entry(card).or_default() += 1;
-                invalid_count += 1;
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum FrameType {
+    Normal,
+    Effect,
+    Ritual,
+    Fusion,
+    Synchro,
+    Xyz,
+    Link,
+    #[name = "Normal Pendulum"]
+    NormalPendulum,
+    #[name = "Effect Pendulum"]
+    EffectPendulum,
+    #[name = "Ritual Pendulum"]
+    RitualPendulum,
+    #[name = "Fusion Pendulum"]
+    FusionPendulum,
+    #[name = "Synchro Pendulum"]
+    SynchroPendulum,
+    #[name = "Xyz Pendulum"]
+    XyzPendulum,
+    Spell,
+    Trap,
+}
+
+impl FrameType {
+    /// The `frameType` string used in the card data.
+    fn as_str(self) -> &'static str {
+        match self {
+            FrameType::Normal => "normal",
+            FrameType::Effect => "effect",
+            FrameType::Ritual => "ritual",
+            FrameType::Fusion => "fusion",
+            FrameType::Synchro => "synchro",
+            FrameType::Xyz => "xyz",
+            FrameType::Link => "link",
+            FrameType::NormalPendulum => "normal_pendulum",
+            FrameType::EffectPendulum => "effect_pendulum",
+            FrameType::RitualPendulum => "ritual_pendulum",
+            FrameType::FusionPendulum => "fusion_pendulum",
+            FrameType::SynchroPendulum => "synchro_pendulum",
+            FrameType::XyzPendulum => "xyz_pendulum",
+            FrameType::Spell => "spell",
+            FrameType::Trap => "trap",
         }
-        (invalids, invalid_count)
     }
 
-    let add_invalids = |name: &str,
-                        invalids: HashMap<&DuelingBookCard, usize>,
-                        invalid_count: usize,
-                        msg: &mut Vec<String>| {
-        if !invalids.is_empty() {
-            msg.push(format!(
-                "## {name} deck includes {invalid_count} invalid cards:"
-            ));
-            let mut entries: Vec<_> = invalids.into_iter().collect();
-            entries.sort_by_key(|(c, _)| &c.name);
-            for (card, count) in entries {
-                msg.push(format!(
-                    "- **{}**{} x {count}",
-                    card.name,
-                    if let Some(name) = &card.username {
-                        format!(" *(custom by: {})*", name)
-                    } else {
-                        String::new()
-                    }
-                ));
+    fn matches(self, card: &CardDatum) -> bool {
+        card.frame_type.eq_ignore_ascii_case(self.as_str())
+    }
+}
+
+/// An inclusive bound on a monster stat. Either end may be left open.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct StatRange {
+    min: Option<i32>,
+    max: Option<i32>,
+}
+
+impl StatRange {
+    fn is_set(&self) -> bool {
+        self.min.is_some() || self.max.is_some()
+    }
+
+    /// Negative stats are "?" in the card data. They are treated as unknown
+    /// rather than as a number, so `atk: -1` does not match `max_atk: 0`;
+    /// they only pass a range when `include_unknown` is set. Cards without
+    /// the stat at all (spells/traps) never pass.
+    fn contains(&self, stat: Option<i32>, include_unknown: bool) -> bool {
+        if !self.is_set() {
+            return true;
+        }
+        match stat {
+            None => false,
+            Some(stat) if is_unknown_stat(stat) => include_unknown,
+            Some(stat) => {
+                self.min.is_none_or(|min| stat >= min) && self.max.is_none_or(|max| stat <= max)
             }
         }
+    }
+}
+
+/// Filters on card properties, applied alongside the name/effect terms.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct CardFilter {
+    card_type: Option<CardType>,
+    frame_type: Option<FrameType>,
+    attribute: Option<Attribute>,
+    /// Any of these levels; empty allows every level.
+    levels: Vec<u32>,
+    min_level: Option<u32>,
+    max_level: Option<u32>,
+    linkval: Option<u32>,
+    /// Bounds on `number_value`, which turn away cards without one.
+    min_passcode: Option<u32>,
+    max_passcode: Option<u32>,
+    atk: StatRange,
+    def: StatRange,
+    include_unknown_stats: bool,
+    /// Only the card named exactly like the name query. Applied in
+    /// `get_reply`, since it concerns the name terms rather than properties.
+    exact_name: bool,
+    /// How the name and effect queries are read, see [`CardFilter::queries`].
+    match_mode: MatchMode,
+    /// Only cards with a "?" ATK or DEF.
+    unknown_stats_only: bool,
+    archetype: Option<String>,
+    /// Part of the monster type or spell/trap kind, in any case.
+    race: Option<String>,
+    /// Part of the full type line, such as "Continuous Spell", in any case.
+    full_type: Option<String>,
+    /// Only cards tagged with this category.
+    category: Option<String>,
+    no_attribute: bool,
+    no_archetype: bool,
+}
+
+fn contains_ignore_case(haystack: &str, needle: &str) -> bool {
+    haystack.to_lowercase().contains(&needle.to_lowercase())
+}
+
+/// Parse a comma-separated list of levels such as "4, 6, 8".
+fn parse_levels(levels: Option<&str>) -> Result<Vec<u32>, anyhow::Error> {
+    levels
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .map(|level| {
+            level
+                .parse()
+                .map_err(|_| anyhow!("`{level}` is not a level"))
+        })
+        .collect()
+}
+
+/// Parse a passcode range such as "10000000-20000000", with either end left
+/// open as in "-20000000", or a single passcode.
+fn parse_passcode_range(range: Option<&str>) -> Result<(Option<u32>, Option<u32>), anyhow::Error> {
+    let Some(range) = range.map(str::trim).filter(|range| !range.is_empty()) else {
+        return Ok((None, None));
+    };
+    let bound = |bound: &str| -> Result<Option<u32>, anyhow::Error> {
+        match bound.trim() {
+            "" => Ok(None),
+            bound => bound.parse().map(Some).map_err(|_| {
+                anyhow!("`{range}` is not a passcode range, such as 10000000-20000000")
+            }),
+        }
     };
+    match range.split_once('-') {
+        Some((min, max)) => Ok((bound(min)?, bound(max)?)),
+        None => {
+            let passcode = bound(range)?;
+            Ok((passcode, passcode))
+        }
+    }
+}
 
-    let (invalid_main, main_count) = track_invalids(&valid_cards, &deck.main);
-    let (invalid_side, side_count) = track_invalids(&valid_cards, &deck.side);
-    let (invalid_extra, extra_count) = track_invalids(&valid_cards, &deck.extra);
+impl CardFilter {
+    /// Parse the name and effect queries according to `match_mode`.
+    fn queries(
+        &self,
+        name: Option<&str>,
+        effect: Option<&str>,
+    ) -> Result<(SearchTerms, SearchTerms), anyhow::Error> {
+        let parse = |query: Option<&str>| {
+            let query = query.unwrap_or_default();
+            match self.match_mode {
+                MatchMode::Substring => Ok(SearchTerms::parse(query)),
+                MatchMode::WholeWords => Ok(SearchTerms::parse(query).whole_words()),
+                MatchMode::Regex => {
+                    let invalid = |reason: String| InvalidPattern {
+                        pattern: query.to_string(),
+                        reason,
+                    };
+                    if query.chars().count() > PATTERN_LENGTH_LIMIT {
+                        return Err(invalid(format!(
+                            "Patterns can be at most {PATTERN_LENGTH_LIMIT} characters long."
+                        )));
+                    }
+                    SearchTerms::regex(query).map_err(|e| invalid(e.to_string()))
+                }
+            }
+        };
+        Ok((parse(name)?, parse(effect)?))
+    }
 
-    let msg = if invalid_main.is_empty() && invalid_side.is_empty() && invalid_extra.is_empty() {
-        "This deck is valid.".to_string()
+    /// Whether `card` passes every filter that is set. Filters that are unset
+    /// let every card through, while a set filter turns away the cards that
+    /// lack the property it checks, such as a level for a Link monster.
+    fn apply(&self, card: &CardDatum) -> bool {
+        self.kind_matches(card)
+            && self.level_matches(card)
+            && self.passcode_matches(card)
+            && self.stats_match(card)
+            && self.tags_match(card)
+    }
+
+    fn kind_matches(&self, card: &CardDatum) -> bool {
+        self.card_type.is_none_or(|ty| ty.matches(card))
+            && self.frame_type.is_none_or(|frame| frame.matches(card))
+            && self.attribute.is_none_or(|attr| attr.matches(card))
+            && (!self.no_attribute || card.attribute.as_deref().is_none_or(str::is_empty))
+    }
+
+    fn level_matches(&self, card: &CardDatum) -> bool {
+        (self.levels.is_empty() || card.level.is_some_and(|level| self.levels.contains(&level)))
+            && self
+                .min_level
+                .is_none_or(|min| card.level.is_some_and(|level| level >= min))
+            && self
+                .max_level
+                .is_none_or(|max| card.level.is_some_and(|level| level <= max))
+            && self
+                .linkval
+                .is_none_or(|linkval| card.linkval == Some(linkval))
+    }
+
+    fn passcode_matches(&self, card: &CardDatum) -> bool {
+        self.min_passcode
+            .is_none_or(|min| card.number_value.is_some_and(|passcode| passcode >= min))
+            && self
+                .max_passcode
+                .is_none_or(|max| card.number_value.is_some_and(|passcode| passcode <= max))
+    }
+
+    fn stats_match(&self, card: &CardDatum) -> bool {
+        self.atk.contains(card.atk, self.include_unknown_stats)
+            && self.def.contains(card.def, self.include_unknown_stats)
+            && (!self.unknown_stats_only
+                || card.atk.is_some_and(is_unknown_stat)
+                || card.def.is_some_and(is_unknown_stat))
+    }
+
+    fn tags_match(&self, card: &CardDatum) -> bool {
+        self.archetype
+            .as_ref()
+            .is_none_or(|archetype| card.archetype.eq_ignore_ascii_case(archetype))
+            && self
+                .race
+                .as_ref()
+                .is_none_or(|race| contains_ignore_case(&card.race, race))
+            && self
+                .full_type
+                .as_ref()
+                .is_none_or(|full_type| contains_ignore_case(&card.full_type, full_type))
+            && self.category.as_ref().is_none_or(|category| {
+                card.category
+                    .iter()
+                    .flatten()
+                    .any(|c| c.eq_ignore_ascii_case(category))
+            })
+            && (!self.no_archetype || card.archetype.is_empty())
+    }
+}
+
+/// The longest regex query accepted, in characters.
+const PATTERN_LENGTH_LIMIT: usize = 200;
+
+/// A regex query that can't be used, reported with its own embed so the
+/// compile error stays readable.
+#[derive(Debug)]
+struct InvalidPattern {
+    pattern: String,
+    reason: String,
+}
+
+impl std::fmt::Display for InvalidPattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` is not a valid regex: {}",
+            self.pattern, self.reason
+        )
+    }
+}
+
+impl std::error::Error for InvalidPattern {}
+
+impl InvalidPattern {
+    fn embed(&self) -> serenity::CreateEmbed {
+        serenity::CreateEmbed::new()
+            .title("Invalid regex")
+            .color(serenity::Color::RED)
+            .description(format!(
+                "```\n{}\n```",
+                self.reason.replace("```", "`\u{200b}``")
+            ))
+            .field(
+                "Pattern",
+                format!("`{}`", self.pattern.replace('`', "'")),
+                false,
+            )
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum MatchMode {
+    /// The `*`, `|` and `-` syntax of [`SearchTerms`] over normalized text.
+    #[default]
+    Substring,
+    /// Like `Substring`, but each term has to match whole words.
+    #[name = "Whole words"]
+    WholeWords,
+    /// A case-insensitive regular expression over the text as written.
+    Regex,
+}
+
+/// How the ATK/DEF filters treat monsters with "?" ATK or DEF.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum UnknownStats {
+    /// Turned away by any ATK/DEF range.
+    #[default]
+    #[name = "Skip in ATK/DEF ranges"]
+    Skip,
+    /// Let through the ATK/DEF ranges.
+    #[name = "Pass ATK/DEF ranges"]
+    Include,
+    /// Only monsters with "?" ATK or DEF.
+    #[name = "Only ? ATK or DEF"]
+    Only,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum SortOrder {
+    Name,
+    #[default]
+    Relevance,
+    #[name = "Passcode (ascending)"]
+    PasscodeAscending,
+    #[name = "Passcode (descending)"]
+    PasscodeDescending,
+    #[name = "Level (highest first)"]
+    Level,
+    #[name = "ATK (highest first)"]
+    Atk,
+    #[name = "DEF (highest first)"]
+    Def,
+    Type,
+}
+
+/// Sort by `key`, then by name, with the cards that have no value for the key
+/// last in either direction.
+fn sort_missing_last<K: Ord>(
+    cards: &mut [&CardDatum],
+    key: impl Fn(&CardDatum) -> Option<K>,
+    descending: bool,
+) {
+    cards.sort_by(|a, b| {
+        let order = match (key(a), key(b)) {
+            (Some(a), Some(b)) if descending => b.cmp(&a),
+            (Some(a), Some(b)) => a.cmp(&b),
+            (a, b) => b.is_some().cmp(&a.is_some()),
+        };
+        order.then_with(|| a.name.cmp(&b.name))
+    })
+}
+
+/// Whether `query` occurs in `name` as a whole word (or run of words).
+fn matches_whole_word(query: &str, name: &str) -> bool {
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    name.match_indices(query).any(|(start, _)| {
+        is_boundary(name[..start].chars().next_back())
+            && is_boundary(name[start + query.len()..].chars().next())
+    })
+}
+
+/// Rank a normalized card name against a normalized query; lower is better.
+/// Exact matches come first, then names containing the query as a whole
+/// word, then prefixes, then everything else, with shorter names winning
+/// ties.
+fn relevance(query: &str, name: &str) -> (u8, usize) {
+    if query.is_empty() {
+        // Nothing to rank by, so leave the alphabetical tiebreak to decide.
+        return (0, 0);
+    }
+    let tier = if name == query {
+        0
+    } else if matches_whole_word(query, name) {
+        1
+    } else if name.starts_with(query) {
+        2
     } else {
-        let mut msg = vec![format!(
-            "# This deck has the following {} invalid cards:",
-            main_count + side_count + extra_count
-        )];
-        add_invalids("Main", invalid_main, main_count, &mut msg);
-        add_invalids("Side", invalid_side, side_count, &mut msg);
-        add_invalids("Extra", invalid_extra, extra_count, &mut msg);
-        msg.join("\n")
+        3
     };
-    ctx.reply(msg).await?;
-    Ok(())
+    (tier, name.len())
 }
 
-fn setup_tracing() -> Result<(), anyhow::Error> {
-    let appender = tracing_appender::rolling::RollingFileAppender::builder()
-        .max_log_files(10)
-        .filename_prefix("rolling")
-        .filename_suffix("log")
-        .rotation(tracing_appender::rolling::Rotation::DAILY)
-        .build("logs")?;
+impl Data {
+    async fn filter_cards(
+        &self,
+        name: Option<&str>,
+        effect: Option<&str>,
+        filter: &CardFilter,
+    ) -> Result<Vec<CardDatum>, anyhow::Error> {
+        let (name, effect) = filter.queries(name, effect)?;
+        let cards = self.cards.get().await;
 
-    let subscriber = Registry::default()
-        .with(
-            // Stdout
-            tracing_subscriber::fmt::layer()
-                .compact()
-                .with_ansi(true)
-                .with_filter(tracing::level_filters::LevelFilter::from_level(
-                    tracing::Level::INFO,
-                )),
+        Ok(cards.matching(&name, &effect, filter).cloned().collect())
+    }
+
+    /// How many cards match, keyed by their `ty`.
+    async fn count_cards(
+        &self,
+        name: Option<&str>,
+        effect: Option<&str>,
+        filter: &CardFilter,
+    ) -> Result<BTreeMap<String, usize>, anyhow::Error> {
+        let (name, effect) = filter.queries(name, effect)?;
+        let cards = self.cards.get().await;
+
+        let mut counts = BTreeMap::new();
+        for card in cards.matching(&name, &effect, filter) {
+            *counts.entry(card.ty.clone()).or_default() += 1;
+        }
+        Ok(counts)
+    }
+
+    /// The full embed for `card`, with its annotation if it has one, and
+    /// its text and art behind spoilers if `spoiler` is set.
+    async fn card_embed(&self, card: &CardDatum, spoiler: bool) -> serenity::CreateEmbed {
+        let annotations = self.annotations.get().await;
+        card.build_embed(
+            spoiler,
+            self.cards.last_success().unwrap_or_default(),
+            annotations.get(&card.name).map(String::as_str),
         )
-        .with(
-            // Rolling logs
-            tracing_subscriber::fmt::layer()
-                .json()
-                .with_writer(appender)
-                .with_filter(
-                    tracing_subscriber::filter::Targets::new()
-                        .with_target("tpp-bot-beard", tracing::Level::TRACE)
-                        .with_default(tracing::Level::DEBUG),
-                ),
-        );
+    }
 
-    tracing::subscriber::set_global_default(subscriber)?;
+    async fn get_reply(
+        &self,
+        card_name: Option<&str>,
+        effect: Option<&str>,
+        filter: &CardFilter,
+        sort: SortOrder,
+    ) -> Result<SearchResults, anyhow::Error> {
+        let (name_terms, effect_terms) = filter.queries(card_name, effect)?;
+        let index = self.cards.get().await;
+        let cards = index.search(card_name, &name_terms, &effect_terms, filter, sort);
+        let suggestions = match card_name {
+            Some(name) if cards.is_empty() => index
+                .suggest(name, SUGGESTION_LIMIT, |card| {
+                    filter.apply(card)
+                        && effect_terms.matches(&card.desc, &normalize_search_term(&card.desc))
+                })
+                .into_iter()
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        };
+        let mut types = BTreeMap::new();
+        for card in &cards {
+            *types.entry(card.ty.clone()).or_default() += 1;
+        }
+        let note = match &cards[..] {
+            [card] => self.annotations.get().await.get(&card.name).cloned(),
+            _ => None,
+        };
+        Ok(SearchResults {
+            note,
+            total: cards.len(),
+            types,
+            cards: cards.into_iter().take(RESULT_LIMIT).cloned().collect(),
+            suggestions,
+            as_of: self.cards.last_success().unwrap_or_default(),
+            art: None,
+        })
+    }
+
+    /// The first of the card's art URLs that can actually be fetched, or the
+    /// preferred one if none can, since the check itself may have failed.
+    async fn reachable_art(&self, card: &CardDatum) -> Option<String> {
+        let candidates = card.art_candidates();
+        for url in &candidates {
+            if self.art_is_reachable(url).await {
+                return Some(url.clone());
+            }
+        }
+        candidates.into_iter().next()
+    }
+
+    /// HEAD-check `url`, remembering the answer for as long as the card data
+    /// is kept before a refresh.
+    async fn art_is_reachable(&self, url: &str) -> bool {
+        if let Some(&(at, reachable)) = self
+            .art_reachability
+            .lock()
+            .expect("art reachability lock poisoned")
+            .get(url)
+        {
+            if at.elapsed() < ART_CHECK_TTL {
+                return reachable;
+            }
+        }
+        let response = reqwest::Client::new()
+            .head(url)
+            .timeout(ART_CHECK_TIMEOUT)
+            .send()
+            .await;
+        match response {
+            Ok(response) => {
+                let reachable = response.status().is_success();
+                self.art_reachability
+                    .lock()
+                    .expect("art reachability lock poisoned")
+                    .insert(url.to_string(), (Instant::now(), reachable));
+                reachable
+            }
+            // Don't remember a failure to ask, it may well be temporary.
+            Err(e) => {
+                tracing::warn!("Could not check art at {url}: {e:#}");
+                false
+            }
+        }
+    }
+}
+
+/// How long a HEAD check of an art URL is trusted, the same as the refresh
+/// window of the card data.
+const ART_CHECK_TTL: Duration = Duration::from_secs(15 * 60);
+/// How long a HEAD check of an art URL may take before it counts as failed.
+const ART_CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Default)]
+struct SearchResults {
+    /// The first [`RESULT_LIMIT`] matches.
+    cards: Vec<CardDatum>,
+    /// How many cards matched in all.
+    total: usize,
+    /// The number of matches of each `ty`.
+    types: BTreeMap<String, usize>,
+    /// Near misses on the name, offered when nothing matched.
+    suggestions: Vec<CardDatum>,
+    /// When the card data the results came from was last refreshed.
+    as_of: chrono::DateTime<Utc>,
+    /// The annotation for the card, when exactly one matched.
+    note: Option<String>,
+    /// Art for the card that is known to load, when exactly one matched and
+    /// [`check_art`] looked for it.
+    art: Option<String>,
+}
+
+impl SearchResults {
+    /// Failed searches, even those with suggestions, are only shown to the
+    /// invoking user so they don't clutter the channel.
+    fn is_ephemeral(&self) -> bool {
+        self.cards.is_empty()
+    }
+}
+
+/// The most "Did you mean" suggestions offered when nothing matched.
+const SUGGESTION_LIMIT: usize = 5;
+
+const PAGE_SIZE: usize = 25;
+/// The most matches kept for paging through.
+const RESULT_LIMIT: usize = 10 * PAGE_SIZE;
+
+fn page_count(cards: &[CardDatum]) -> usize {
+    cards.len().div_ceil(PAGE_SIZE)
+}
+
+/// Build the embed for the given search results, showing `page` of the
+/// "Did you mean" listing when there is more than one match.
+fn reply_embed(results: &SearchResults, page: usize) -> serenity::CreateEmbed {
+    let cards = &results.cards;
+    match cards.len() {
+        0 if !results.suggestions.is_empty() => serenity::CreateEmbed::new()
+            .title("No cards found".to_string())
+            .description(format!(
+                "Did you mean: \n- {}",
+                results
+                    .suggestions
+                    .iter()
+                    .map(|card| card.name.to_string())
+                    .collect::<Vec<_>>()
+                    .join("\n- ")
+            )),
+        0 => serenity::CreateEmbed::new()
+            .title("No cards found".to_string())
+            .description("No cards were found that match the provided filters"),
+        1 => {
+            let embed = cards[0].make_embed(results.as_of, results.note.as_deref());
+            match &results.art {
+                Some(art) => embed.image(art),
+                None => embed,
+            }
+        }
+        _ => {
+            // Point broad searches at ways to narrow them down.
+            let summary = if results.total > PAGE_SIZE {
+                let types = results
+                    .types
+                    .iter()
+                    .map(|(ty, count)| format!("{count} {ty}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!(
+                    "**{} matches** ({types}). Add more terms or filters to narrow the search.\n",
+                    results.total
+                )
+            } else {
+                String::new()
+            };
+            let shown = if results.total > cards.len() {
+                format!("{} matches, showing {}", results.total, cards.len())
+            } else {
+                format!("{} matches", results.total)
+            };
+            serenity::CreateEmbed::new()
+                .title("Multiple matches found".to_string())
+                .description(format!(
+                    "{summary}Did you mean: \n- {}",
+                    cards
+                        .iter()
+                        .skip(page * PAGE_SIZE)
+                        .take(PAGE_SIZE)
+                        .map(|card| card.list_entry())
+                        .collect::<Vec<_>>()
+                        .join("\n- ")
+                ))
+                .footer(serenity::CreateEmbedFooter::new(format!(
+                    "Page {}/{} ({shown})",
+                    page + 1,
+                    page_count(cards),
+                )))
+        }
+    }
+}
 
+/// Look up art known to load for a lone match, deferring the reply first
+/// since the checks can take longer than Discord waits for a response.
+async fn check_art(ctx: Context<'_>, results: &mut SearchResults) -> Result<(), anyhow::Error> {
+    if let [card] = &results.cards[..] {
+        ctx.defer().await?;
+        results.art = ctx.data().reachable_art(card).await;
+    }
     Ok(())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), anyhow::Error> {
-    setup_tracing()?;
-    dotenv::dotenv()?;
+/// Send the search results, letting the invoking user flip through the
+/// listing with buttons when it spans more than one page. Failed searches
+/// are only shown to the invoking user, see [`SearchResults::is_ephemeral`].
+/// With `spoiler` set, a card picked from the listing is shown spoilered.
+async fn paginate_matches(
+    ctx: Context<'_>,
+    results: &SearchResults,
+    spoiler: bool,
+) -> Result<(), anyhow::Error> {
+    paginate(
+        ctx,
+        page_count(&results.cards),
+        results.is_ephemeral(),
+        &results.cards,
+        spoiler,
+        |page| reply_embed(results, page),
+    )
+    .await
+}
 
-    let token = std::env::var("DISCORD_TOKEN")?;
-    let intents =
-        serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
+/// Width of the name column in `/search list`, chosen so lines fit on a
+/// phone screen without wrapping.
+const LISTING_NAME_WIDTH: usize = 24;
+/// Width of the monster type column in `/search list`.
+const LISTING_RACE_WIDTH: usize = 12;
+/// The most messages `/search list` sends for one search.
+const LISTING_MESSAGE_LIMIT: usize = 5;
 
-    let framework = poise::Framework::builder()
-        .options(poise::FrameworkOptions {
-            commands: vec![search(), check_deck()],
-            event_handler: |ctx, event, framework, data| {
-                Box::pin(event_handler(ctx, event, framework, data))
-            },
+/// Pad `text` to `width` characters, cutting it short with an ellipsis if it
+/// is longer.
+fn fit(text: &str, width: usize) -> String {
+    if text.chars().count() > width {
+        let cut: String = text.chars().take(width - 1).collect();
+        format!("{cut}…")
+    } else {
+        format!("{text:<width$}")
+    }
+}
 
-            ..Default::default()
-        })
-        .setup(|ctx, _ready, framework| {
-            Box::pin(async move {
-                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                Ok(Data {
-                    cards: FreshData::new(chrono::Duration::minutes(15), || {
-                        Box::pin(async move {
-                            reqwest::get(CARD_DATA)
-                                .await
-                                .expect("Could not fetch new card data")
-                                .json()
-                                .await
-                                .expect("could not decode card data")
-                        })
-                    })
-                    .await,
-                })
+/// Send one table row per card, split over as many embeds as the 4096
+/// character description limit needs, up to [`LISTING_MESSAGE_LIMIT`].
+async fn send_listing(ctx: Context<'_>, results: &SearchResults) -> Result<(), anyhow::Error> {
+    const DESCRIPTION_LIMIT: usize = 4096;
+    // The code block fence around each chunk, plus a line for the remainder.
+    const OVERHEAD: usize = 8 + 64;
+
+    let mut chunks = vec![String::new()];
+    let mut listed = 0;
+    for card in &results.cards {
+        let row = card.listing_row();
+        let chunk = chunks.last_mut().expect("there is always a chunk");
+        if chunk.chars().count() + row.chars().count() + 1 + OVERHEAD > DESCRIPTION_LIMIT {
+            if chunks.len() == LISTING_MESSAGE_LIMIT {
+                break;
+            }
+            chunks.push(String::new());
+        }
+        let chunk = chunks.last_mut().expect("there is always a chunk");
+        chunk.push_str(&row);
+        chunk.push('\n');
+        listed += 1;
+    }
+
+    let count = chunks.len();
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        let mut description = format!("```\n{chunk}```");
+        if i + 1 == count && listed < results.total {
+            description.push_str(&format!("…and {} more", results.total - listed));
+        }
+        let embed = serenity::CreateEmbed::new()
+            .title(match results.total {
+                1 => "1 match".to_string(),
+                n => format!("{n} matches"),
             })
-        })
-        .build();
-    let mut client = serenity::ClientBuilder::new(token, intents)
-        .framework(framework)
+            .description(description)
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "Part {}/{count}",
+                i + 1
+            )));
+        ctx.send(CreateReply::default().embed(embed)).await?;
+    }
+    Ok(())
+}
+
+/// The most options Discord allows in a select menu.
+const SELECT_LIMIT: usize = 25;
+
+/// Send the first of `pages` embeds built by `render`, with buttons for the
+/// invoking user to flip between them for a minute. The buttons are disabled
+/// once that minute is up.
+///
+/// When there are at least two `choices`, a select menu of the first
+/// [`SELECT_LIMIT`] of them is attached too, and picking one replaces the
+/// listing with that card's embed, spoilered if `spoiler` is set.
+async fn paginate(
+    ctx: Context<'_>,
+    pages: usize,
+    ephemeral: bool,
+    choices: &[CardDatum],
+    spoiler: bool,
+    render: impl Fn(usize) -> serenity::CreateEmbed,
+) -> Result<(), anyhow::Error> {
+    let ctx_id = ctx.id();
+    let prev_button_id = format!("{ctx_id}prev");
+    let next_button_id = format!("{ctx_id}next");
+    let pick_id = format!("{ctx_id}pick");
+    let choices = if choices.len() >= 2 {
+        &choices[..choices.len().min(SELECT_LIMIT)]
+    } else {
+        &[]
+    };
+    // Select option values are limited to 100 characters.
+    let select_value = |card: &CardDatum| card.name.chars().take(100).collect::<String>();
+    let components = |disabled| {
+        let mut rows = Vec::new();
+        if pages > 1 {
+            rows.push(serenity::CreateActionRow::Buttons(vec![
+                serenity::CreateButton::new(&prev_button_id)
+                    .emoji('◀')
+                    .disabled(disabled),
+                serenity::CreateButton::new(&next_button_id)
+                    .emoji('▶')
+                    .disabled(disabled),
+            ]));
+        }
+        if !choices.is_empty() {
+            let options = choices
+                .iter()
+                .map(|card| {
+                    let value = select_value(card);
+                    serenity::CreateSelectMenuOption::new(value.clone(), value)
+                })
+                .collect();
+            rows.push(serenity::CreateActionRow::SelectMenu(
+                serenity::CreateSelectMenu::new(
+                    &pick_id,
+                    serenity::CreateSelectMenuKind::String { options },
+                )
+                .placeholder("Show a card")
+                .disabled(disabled),
+            ));
+        }
+        rows
+    };
+
+    if components(false).is_empty() {
+        ctx.send(CreateReply::default().embed(render(0)).ephemeral(ephemeral))
+            .await?;
+        return Ok(());
+    }
+
+    let reply = ctx
+        .send(
+            CreateReply::default()
+                .embed(render(0))
+                .ephemeral(ephemeral)
+                .components(components(false)),
+        )
         .await?;
-    client.start().await?;
 
+    let mut page = 0;
+    while let Some(press) = serenity::collector::ComponentInteractionCollector::new(ctx)
+        .author_id(ctx.author().id)
+        .filter(move |press| press.data.custom_id.starts_with(&ctx_id.to_string()))
+        .timeout(std::time::Duration::from_secs(60))
+        .await
+    {
+        if press.data.custom_id == next_button_id {
+            page = (page + 1) % pages;
+        } else if press.data.custom_id == prev_button_id {
+            page = page.checked_sub(1).unwrap_or(pages - 1);
+        } else if press.data.custom_id == pick_id {
+            let serenity::ComponentInteractionDataKind::StringSelect { values } = &press.data.kind
+            else {
+                continue;
+            };
+            let Some(card) = values
+                .first()
+                .and_then(|name| choices.iter().find(|card| select_value(card) == **name))
+            else {
+                continue;
+            };
+            press
+                .create_response(
+                    ctx.serenity_context(),
+                    serenity::CreateInteractionResponse::UpdateMessage(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .embed(ctx.data().card_embed(card, spoiler).await)
+                            .components(Vec::new()),
+                    ),
+                )
+                .await?;
+            return Ok(());
+        } else {
+            continue;
+        }
+
+        press
+            .create_response(
+                ctx.serenity_context(),
+                serenity::CreateInteractionResponse::UpdateMessage(
+                    serenity::CreateInteractionResponseMessage::new().embed(render(page)),
+                ),
+            )
+            .await?;
+    }
+
+    // Editing replaces the embeds too, so resend the page being shown.
+    reply
+        .edit(
+            ctx,
+            CreateReply::default()
+                .embed(render(page))
+                .components(components(true)),
+        )
+        .await?;
     Ok(())
 }
+
+type Context<'a> = poise::Context<'a, Data, anyhow::Error>;
+
+/// Like poise's default handler, but command errors are only shown to the
+/// invoking user.
+async fn on_error(error: poise::FrameworkError<'_, Data, anyhow::Error>) {
+    match error {
+        poise::FrameworkError::Command { ctx, error, .. } => {
+            tracing::error!("Error in command `{}`: {error:#}", ctx.command().name);
+            // Errors can quote user-supplied text, such as deck contents.
+            let reply = match error.downcast_ref::<InvalidPattern>() {
+                Some(invalid) => CreateReply::default().embed(invalid.embed()),
+                None => CreateReply::default().content(error.to_string()),
+            };
+            let reply = reply
+                .ephemeral(true)
+                .allowed_mentions(CreateAllowedMentions::new());
+            if let Err(e) = ctx.send(reply).await {
+                tracing::warn!("Could not report command error: {e}");
+            }
+        }
+        error => {
+            if let Err(e) = poise::builtins::on_error(error).await {
+                tracing::error!("Error while handling error: {e}");
+            }
+        }
+    }
+}
+
+/// Set once the missing MESSAGE_CONTENT intent has been reported, so the
+/// warning is logged once per session rather than on every message.
+static WARNED_MISSING_CONTENT: AtomicBool = AtomicBool::new(false);
+
+/// Whether `message` looks like it had its content blanked. Only messages
+/// typed by people count, since system messages and bot messages carrying
+/// just an embed are legitimately empty.
+fn lacks_content(message: &serenity::Message) -> bool {
+    matches!(
+        message.kind,
+        serenity::MessageType::Regular | serenity::MessageType::InlineReply
+    ) && !message.author.bot
+        && message.content.is_empty()
+        && message.attachments.is_empty()
+        && message.embeds.is_empty()
+        && message.sticker_items.is_empty()
+}
+
+/// Without the privileged MESSAGE_CONTENT intent Discord delivers messages
+/// with their content blanked, which silently disables the `<...>` lookup.
+/// Warns the first time such a message arrives while `warned` is unset,
+/// returning whether it did.
+fn warn_if_content_missing(message: &serenity::Message, warned: &AtomicBool) -> bool {
+    if lacks_content(message) && !warned.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            "Received a message with no content. The MESSAGE_CONTENT intent must be enabled \
+             for this bot in the Discord developer portal, or `<card name>` lookups will not work."
+        );
+        return true;
+    }
+    false
+}
+
+async fn event_handler(
+    ctx: &serenity::Context,
+    event: &serenity::FullEvent,
+    _framework: poise::FrameworkContext<'_, Data, anyhow::Error>,
+    data: &Data,
+) -> Result<(), anyhow::Error> {
+    if let serenity::FullEvent::Message { new_message } = event {
+        warn_if_content_missing(new_message, &WARNED_MISSING_CONTENT);
+        let lookups = inline_lookups(&new_message.content);
+        let whole_message =
+            lookups.len() == 1 && new_message.content.trim() == format!("<{}>", lookups[0]);
+        let mut embeds = Vec::new();
+        for name in lookups {
+            let results = data
+                .get_reply(
+                    Some(name),
+                    None,
+                    &CardFilter::default(),
+                    SortOrder::default(),
+                )
+                .await?;
+            // A message that is nothing but a lookup gets the listing or the
+            // suggestions too, showing only the first page since nobody is
+            // around to page through it. Lookups within a sentence only
+            // answer for the ones that found their card, to keep the noise
+            // down.
+            if whole_message || results.cards.len() == 1 {
+                embeds.push(reply_embed(&results, 0));
+            }
+        }
+        if !embeds.is_empty() {
+            let builder = CreateMessage::new()
+                .embeds(embeds)
+                .reference_message(new_message)
+                // The reply echoes card names picked by whoever typed the
+                // message, so it must not ping anyone.
+                .allowed_mentions(CreateAllowedMentions::new().replied_user(false));
+            new_message.channel_id.send_message(ctx, builder).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The most cards a single message can look up inline.
+const INLINE_LOOKUP_LIMIT: usize = 3;
+/// The most `<...>` spans of a message that are searched for, so a message
+/// full of brackets can't set off a search for each.
+const INLINE_SPAN_LIMIT: usize = 10;
+
+static INLINE_SPAN: OnceLock<Regex> = OnceLock::new();
+
+/// The first [`INLINE_LOOKUP_LIMIT`] distinct card names written as `<name>`
+/// in `content`, leaving out Discord's own markup in angle brackets: user,
+/// role and channel mentions, custom emoji, timestamps, slash command
+/// mentions and unembedded links.
+fn inline_lookups(content: &str) -> Vec<&str> {
+    let span = INLINE_SPAN
+        .get_or_init(|| Regex::new(r"<([^<>\n]+)>").expect("Cannot compile inline span re"));
+    let mut names: Vec<&str> = Vec::new();
+    for capture in span.captures_iter(content).take(INLINE_SPAN_LIMIT) {
+        let Some(name) = capture.get(1).map(|name| name.as_str().trim()) else {
+            continue;
+        };
+        let is_markup = name.starts_with(['@', '#', ':', '/'])
+            || name.starts_with("a:")
+            || name.starts_with("t:")
+            || name.starts_with("http://")
+            || name.starts_with("https://");
+        if !name.is_empty() && !is_markup && !names.contains(&name) {
+            names.push(name);
+            if names.len() == INLINE_LOOKUP_LIMIT {
+                break;
+            }
+        }
+    }
+    names
+}
+
+/// Rank a normalized name for the normalized autocomplete `partial`, lower
+/// first: names starting with it, then names with a word starting with it,
+/// then names containing it anywhere. `None` if it doesn't occur at all.
+fn autocomplete_rank(partial: &str, name: &str) -> Option<u8> {
+    let starts_word = |i: usize| {
+        name[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric())
+    };
+    let positions: Vec<usize> = name.match_indices(partial).map(|(i, _)| i).collect();
+    match positions.first()? {
+        0 => Some(0),
+        _ if positions.iter().any(|&i| starts_word(i)) => Some(1),
+        _ => Some(2),
+    }
+}
+
+/// Added to the rank of names suggested for matching an alias, which puts them
+/// after all of the `autocomplete_rank` tiers.
+const ALIAS_RANK_OFFSET: u8 = 3;
+
+/// The most autocomplete choices Discord will show.
+const AUTOCOMPLETE_LIMIT: usize = 25;
+
+async fn autocomplete_search(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    // Every name matches an empty partial, so wait for something to go on.
+    if partial.trim().is_empty() {
+        return Vec::new();
+    }
+    let data = ctx.data();
+    // Match the way the search itself does, so a picked name finds its card.
+    let partial = normalize_search_term(partial.trim());
+    // Only suggest names the search would find with the effect given so far.
+    let effect = sibling_option(ctx, "effect").unwrap_or_default();
+    let key = format!("{partial}\0{effect}");
+    let cards = data.cards.get().await;
+    let generation = data.cards.generation();
+    if let Some(hit) = data
+        .autocomplete_cache
+        .lock()
+        .expect("autocomplete cache poisoned")
+        .get(generation, &key)
+    {
+        return hit;
+    }
+
+    let result = autocomplete_names(&cards, &partial, &SearchTerms::parse(effect));
+    data.autocomplete_cache
+        .lock()
+        .expect("autocomplete cache poisoned")
+        .insert(generation, key, result.clone());
+    result
+}
+
+/// Up to [`AUTOCOMPLETE_LIMIT`] names for the normalized `partial`, from the
+/// cards whose text matches `effect`, best first.
+fn autocomplete_names(cards: &CardIndex, partial: &str, effect: &SearchTerms) -> Vec<String> {
+    let mut ranked = cards
+        .entries_with_aliases()
+        .filter(|(card, _, desc, _)| effect.matches(&card.desc, desc))
+        .filter_map(|(card, name, _, aliases)| {
+            // Offer the real name either way, but below every card whose own
+            // name matches.
+            let rank = autocomplete_rank(partial, name).or_else(|| {
+                aliases
+                    .iter()
+                    .filter_map(|alias| autocomplete_rank(partial, alias))
+                    .min()
+                    .map(|rank| rank + ALIAS_RANK_OFFSET)
+            })?;
+            Some((rank, &card.name))
+        })
+        .collect::<Vec<_>>();
+    ranked.sort();
+    if ranked.is_empty() {
+        // Nothing contains the partial, perhaps because of a typo, so offer
+        // the closest names instead, in order of closeness.
+        ranked = cards
+            .suggest(partial, AUTOCOMPLETE_LIMIT, |card| {
+                effect.matches(&card.desc, &normalize_search_term(&card.desc))
+            })
+            .into_iter()
+            .map(|card| (0, &card.name))
+            .collect();
+    }
+    ranked
+        .into_iter()
+        .take(AUTOCOMPLETE_LIMIT)
+        // Discord rejects longer choices.
+        .map(|(_, name)| name.chars().take(100).collect())
+        .collect()
+}
+
+/// The value already filled in for another string option of the command
+/// being autocompleted.
+fn sibling_option<'a>(ctx: Context<'a>, name: &str) -> Option<&'a str> {
+    let poise::Context::Application(ctx) = ctx else {
+        return None;
+    };
+    ctx.interaction
+        .data
+        .options
+        .iter()
+        .find(|option| option.name == name)
+        .and_then(|option| option.value.as_str())
+}
+
+/// Distinct non-empty values of a card field that start with `partial`,
+/// for autocompleting filters over free-form fields.
+async fn autocomplete_field(
+    ctx: Context<'_>,
+    partial: &str,
+    field: fn(&CardDatum) -> &[String],
+) -> Vec<String> {
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .cards
+        .get()
+        .await
+        .iter()
+        .flat_map(field)
+        .filter(|v| !v.is_empty() && v.to_lowercase().starts_with(&partial))
+        .cloned()
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .take(AUTOCOMPLETE_LIMIT)
+        .collect()
+}
+
+async fn autocomplete_archetype(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    autocomplete_field(ctx, partial, |c| std::slice::from_ref(&c.archetype)).await
+}
+
+async fn autocomplete_race(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    autocomplete_field(ctx, partial, |c| std::slice::from_ref(&c.race)).await
+}
+
+async fn autocomplete_full_type(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    autocomplete_field(ctx, partial, |c| std::slice::from_ref(&c.full_type)).await
+}
+
+async fn autocomplete_category(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    autocomplete_field(ctx, partial, |c| c.category.as_deref().unwrap_or_default()).await
+}
+
+/// The filter options `/search` and `/count` share, turned into a
+/// [`CardFilter`] in one place so the two commands read them the same way.
+#[derive(Debug, Default)]
+struct FilterArgs {
+    card_type: Option<CardType>,
+    frame_type: Option<FrameType>,
+    attribute: Option<Attribute>,
+    level: Option<String>,
+    min_level: Option<u32>,
+    max_level: Option<u32>,
+    linkval: Option<u32>,
+    passcode: Option<String>,
+    min_atk: Option<i32>,
+    max_atk: Option<i32>,
+    min_def: Option<i32>,
+    max_def: Option<i32>,
+    unknown_stats: Option<UnknownStats>,
+    archetype: Option<String>,
+    race: Option<String>,
+    full_type: Option<String>,
+    category: Option<String>,
+    no_attribute: Option<bool>,
+    match_mode: Option<MatchMode>,
+}
+
+impl FilterArgs {
+    fn filter(self) -> Result<CardFilter, anyhow::Error> {
+        let no_archetype = self
+            .archetype
+            .as_deref()
+            .is_some_and(|archetype| archetype.eq_ignore_ascii_case("none"));
+        let (min_passcode, max_passcode) = parse_passcode_range(self.passcode.as_deref())?;
+        let unknown_stats = self.unknown_stats.unwrap_or_default();
+        Ok(CardFilter {
+            card_type: self.card_type,
+            frame_type: self.frame_type,
+            attribute: self.attribute,
+            levels: parse_levels(self.level.as_deref())?,
+            min_level: self.min_level,
+            max_level: self.max_level,
+            linkval: self.linkval,
+            min_passcode,
+            max_passcode,
+            atk: StatRange {
+                min: self.min_atk,
+                max: self.max_atk,
+            },
+            def: StatRange {
+                min: self.min_def,
+                max: self.max_def,
+            },
+            include_unknown_stats: unknown_stats == UnknownStats::Include,
+            exact_name: false,
+            match_mode: self.match_mode.unwrap_or_default(),
+            unknown_stats_only: unknown_stats == UnknownStats::Only,
+            no_archetype,
+            archetype: self.archetype.filter(|_| !no_archetype),
+            race: self.race,
+            full_type: self.full_type,
+            category: self.category,
+            no_attribute: self.no_attribute.unwrap_or_default(),
+        })
+    }
+}
+
+#[poise::command(slash_command)]
+#[allow(clippy::too_many_arguments)]
+/// Search for a card in the TPP format. Terms separated by * must appear in that order.
+///
+/// Separate alternatives with |, which binds tighter than *, so
+/// `banish * shuffle | return` needs "banish" followed by either of the others.
+/// Start a term with - to exclude cards containing it, e.g.
+/// `destroy * -cannot be destroyed`. Wrap a phrase in double quotes to
+/// match * | and - literally inside it.
+///
+/// The "Whole words" match mode keeps `set` from matching "reset". Terms and
+/// card text have their punctuation normalized away before comparing, so
+/// `heros` and `hero's` are the same word.
+///
+/// ATK/DEF ranges skip monsters with ? stats unless `unknown_stats` lets
+/// them through, and never match spells or traps.
+async fn search(
+    ctx: Context<'_>,
+    #[description = "Card Name"]
+    #[autocomplete = autocomplete_search]
+    name: Option<String>,
+    #[description = "Card Effect"] effect: Option<String>,
+    #[description = "Card Type"] card_type: Option<CardType>,
+    #[description = "Card Frame"] frame_type: Option<FrameType>,
+    #[description = "Monster Attribute"] attribute: Option<Attribute>,
+    #[description = "Monster Level, or several separated by commas"] level: Option<String>,
+    #[description = "Minimum Level"] min_level: Option<u32>,
+    #[description = "Maximum Level"] max_level: Option<u32>,
+    #[description = "Link Rating"] linkval: Option<u32>,
+    #[description = "Passcode range, such as 10000000-20000000, with either end optional"]
+    passcode: Option<String>,
+    #[description = "Minimum ATK"] min_atk: Option<i32>,
+    #[description = "Maximum ATK"] max_atk: Option<i32>,
+    #[description = "Minimum DEF"] min_def: Option<i32>,
+    #[description = "Maximum DEF"] max_def: Option<i32>,
+    #[description = "How monsters with ? ATK/DEF are treated"] unknown_stats: Option<UnknownStats>,
+    #[description = "Card Archetype, or \"none\" for cards without one"]
+    #[autocomplete = autocomplete_archetype]
+    archetype: Option<String>,
+    #[description = "Monster type, or spell/trap kind such as Counter"]
+    #[autocomplete = autocomplete_race]
+    race: Option<String>,
+    #[description = "Full type line, such as Continuous Spell or Tuner"]
+    #[autocomplete = autocomplete_full_type]
+    full_type: Option<String>,
+    #[description = "Card Category"]
+    #[autocomplete = autocomplete_category]
+    category: Option<String>,
+    #[description = "Only cards without an attribute"] no_attribute: Option<bool>,
+    #[description = "Order of the match listing"] sort: Option<SortOrder>,
+    #[description = "Hide the card text and art behind spoilers"] spoiler: Option<bool>,
+    #[description = "Only match the card with exactly this name"] exact: Option<bool>,
+    #[description = "List every match with its stats, even a single card"] list: Option<bool>,
+    #[description = "How name and effect are matched, e.g. as a regex over the card text"]
+    match_mode: Option<MatchMode>,
+) -> Result<(), anyhow::Error> {
+    let filter = CardFilter {
+        exact_name: exact.unwrap_or_default(),
+        ..FilterArgs {
+            card_type,
+            frame_type,
+            attribute,
+            level,
+            min_level,
+            max_level,
+            linkval,
+            passcode,
+            min_atk,
+            max_atk,
+            min_def,
+            max_def,
+            unknown_stats,
+            archetype,
+            race,
+            full_type,
+            category,
+            no_attribute,
+            match_mode,
+        }
+        .filter()?
+    };
+    let mut results = ctx
+        .data()
+        .get_reply(
+            name.as_deref(),
+            effect.as_deref(),
+            &filter,
+            sort.unwrap_or_default(),
+        )
+        .await?;
+    if list.unwrap_or_default() && !results.cards.is_empty() {
+        return send_listing(ctx, &results).await;
+    }
+    if let [card] = &results.cards[..] {
+        if spoiler.unwrap_or_default() {
+            ctx.send(
+                CreateReply::default()
+                    .embed(card.make_spoiler_embed(results.as_of, results.note.as_deref())),
+            )
+            .await?;
+            return Ok(());
+        }
+    }
+    check_art(ctx, &mut results).await?;
+    paginate_matches(ctx, &results, spoiler.unwrap_or_default()).await
+}
+
+#[poise::command(slash_command, rename = "query")]
+/// Search with one string, e.g. atk>=2000 level<=4 attr:dark race:warrior "special summon"
+///
+/// Filters are written `key:value`, and level, passcode, atk and def also
+/// take <, <=, > and >=. Quote a value to include spaces. Every other word
+/// is part of the card name. The keys are name, effect, type, frame, attr, race,
+/// archetype, category, full_type, level, link, passcode, atk and def.
+async fn query_search(
+    ctx: Context<'_>,
+    #[description = "The query, such as atk>=2000 attr:dark \"special summon\""] query: String,
+    #[description = "Order of the match listing"] sort: Option<SortOrder>,
+) -> Result<(), anyhow::Error> {
+    let query::Query {
+        name,
+        effect,
+        filter,
+    } = query::parse(&query)?;
+    let mut results = ctx
+        .data()
+        .get_reply(
+            Some(name.as_str()).filter(|name| !name.is_empty()),
+            Some(effect.as_str()).filter(|effect| !effect.is_empty()),
+            &filter,
+            sort.unwrap_or_default(),
+        )
+        .await?;
+    check_art(ctx, &mut results).await?;
+    paginate_matches(ctx, &results, false).await
+}
+
+#[poise::command(slash_command)]
+/// Show a card as plain text instead of an embed, for screen readers.
+async fn text(
+    ctx: Context<'_>,
+    #[description = "Card Name"]
+    #[autocomplete = autocomplete_search]
+    name: String,
+) -> Result<(), anyhow::Error> {
+    let results = ctx
+        .data()
+        .get_reply(
+            Some(&name),
+            None,
+            &CardFilter::default(),
+            SortOrder::default(),
+        )
+        .await?;
+    let [card] = &results.cards[..] else {
+        return paginate_matches(ctx, &results, false).await;
+    };
+    for piece in split_message(&card.plain_text(results.note.as_deref())) {
+        ctx.send(
+            CreateReply::default()
+                .content(piece)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+/// Compare two cards side by side.
+async fn compare(
+    ctx: Context<'_>,
+    #[description = "First card"]
+    #[autocomplete = autocomplete_search]
+    first: String,
+    #[description = "Second card"]
+    #[autocomplete = autocomplete_search]
+    second: String,
+) -> Result<(), anyhow::Error> {
+    let data = ctx.data();
+    let mut cards = Vec::new();
+    for name in [&first, &second] {
+        let results = data
+            .get_reply(
+                Some(name),
+                None,
+                &CardFilter::default(),
+                SortOrder::default(),
+            )
+            .await?;
+        match <[CardDatum; 1]>::try_from(results.cards) {
+            Ok([card]) => cards.push(card),
+            Err(matches) => {
+                let results = SearchResults {
+                    cards: matches,
+                    ..results
+                };
+                return paginate_matches(ctx, &results, false).await;
+            }
+        }
+    }
+
+    let embed = serenity::CreateEmbed::new()
+        .title(format!("{} vs {}", cards[0].name, cards[1].name))
+        .color(serenity::Color::DARK_GREY)
+        .field(cards[0].name.clone(), cards[0].stat_lines(), true)
+        .field(cards[1].name.clone(), cards[1].stat_lines(), true);
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+/// Pull a random card from the TPP format.
+async fn random(
+    ctx: Context<'_>,
+    #[description = "Card Type"] card_type: Option<CardType>,
+    #[description = "Card Attribute"] attribute: Option<Attribute>,
+    #[description = "Card Archetype"]
+    #[autocomplete = autocomplete_archetype]
+    archetype: Option<String>,
+) -> Result<(), anyhow::Error> {
+    let filter = CardFilter {
+        card_type,
+        attribute,
+        archetype,
+        ..Default::default()
+    };
+    let embed = {
+        let cards = ctx.data().cards.get().await;
+        let card = pick_random(cards.iter(), &filter, &mut rand::thread_rng());
+        match card {
+            Some(card) => ctx.data().card_embed(card, false).await,
+            None => reply_embed(&SearchResults::default(), 0),
+        }
+    };
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Choose uniformly among the cards matching `filter`.
+fn pick_random<'a>(
+    cards: impl Iterator<Item = &'a CardDatum>,
+    filter: &CardFilter,
+    rng: &mut impl rand::Rng,
+) -> Option<&'a CardDatum> {
+    let pool: Vec<_> = cards.filter(|card| filter.apply(card)).collect();
+    pool.choose(rng).copied()
+}
+
+/// Which of `len` cards, sorted by name, is the card of the day on `date`.
+/// Uses FNV-1a rather than the std hasher, whose output may change between
+/// Rust releases, so the pick is stable across restarts and upgrades.
+fn card_of_the_day_index(date: chrono::NaiveDate, len: usize) -> usize {
+    let hash = date
+        .format("%Y-%m-%d")
+        .to_string()
+        .bytes()
+        .fold(0xcbf29ce484222325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+        });
+    (hash % len as u64) as usize
+}
+
+/// The card of the day on `date`, picked from `cards` sorted by name so the
+/// order the card data lists them in doesn't matter.
+fn card_of_the_day<'a>(
+    cards: impl Iterator<Item = &'a CardDatum>,
+    date: chrono::NaiveDate,
+) -> Option<&'a CardDatum> {
+    let mut sorted: Vec<_> = cards.collect();
+    sorted.sort_by(|a, b| a.name.cmp(&b.name));
+    if sorted.is_empty() {
+        return None;
+    }
+    Some(sorted[card_of_the_day_index(date, sorted.len())])
+}
+
+#[poise::command(slash_command)]
+/// Show today's card of the day, the same for everyone until midnight UTC.
+async fn cotd(ctx: Context<'_>) -> Result<(), anyhow::Error> {
+    let embed = {
+        let cards = ctx.data().cards.get().await;
+        match card_of_the_day(cards.iter(), Utc::now().date_naive()) {
+            Some(card) => ctx.data().card_embed(card, false).await,
+            None => reply_embed(&SearchResults::default(), 0),
+        }
+    };
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+fn empty_archetype_embed(archetype: &str) -> serenity::CreateEmbed {
+    serenity::CreateEmbed::new()
+        .title("No cards found".to_string())
+        .description(format!("No cards belong to the archetype {archetype}"))
+}
+
+/// A link to the TPP site with `params` filled in as query pairs, skipping
+/// the ones that aren't set.
+fn search_permalink(params: &[(&str, Option<&str>)]) -> String {
+    let mut url = reqwest::Url::parse(SITE_URL).expect("must be valid");
+    {
+        let mut query = url.query_pairs_mut();
+        for (key, value) in params {
+            if let Some(value) = value.filter(|value| !value.trim().is_empty()) {
+                query.append_pair(key, value.trim());
+            }
+        }
+    }
+    // Drop the dangling `?` when nothing was set.
+    if url.query() == Some("") {
+        url.set_query(None);
+    }
+    url.to_string()
+}
+
+#[poise::command(slash_command)]
+/// Get a link to the TPP site with a search filled in, to share it.
+#[allow(clippy::too_many_arguments)]
+async fn link(
+    ctx: Context<'_>,
+    #[description = "Card Name"] name: Option<String>,
+    #[description = "Card Effect"] effect: Option<String>,
+    #[description = "Card Type"] card_type: Option<CardType>,
+    #[description = "Card Frame"] frame_type: Option<FrameType>,
+    #[description = "Monster Attribute"] attribute: Option<Attribute>,
+    #[description = "Monster Level, or several separated by commas"] level: Option<String>,
+    #[description = "Card Archetype"]
+    #[autocomplete = autocomplete_archetype]
+    archetype: Option<String>,
+    #[description = "Monster type, or spell/trap kind such as Counter"]
+    #[autocomplete = autocomplete_race]
+    race: Option<String>,
+    #[description = "Full type line, such as Continuous Spell or Tuner"]
+    #[autocomplete = autocomplete_full_type]
+    full_type: Option<String>,
+    #[description = "Card Category"]
+    #[autocomplete = autocomplete_category]
+    category: Option<String>,
+) -> Result<(), anyhow::Error> {
+    // Catch typos here rather than on the site.
+    parse_levels(level.as_deref())?;
+    let url = search_permalink(&[
+        ("name", name.as_deref()),
+        ("effect", effect.as_deref()),
+        ("card_type", card_type.map(|ty| ty.name())),
+        ("frame_type", frame_type.map(FrameType::as_str)),
+        ("attribute", attribute.map(|attr| attr.name())),
+        ("level", level.as_deref()),
+        ("archetype", archetype.as_deref()),
+        ("race", race.as_deref()),
+        ("full_type", full_type.as_deref()),
+        ("category", category.as_deref()),
+    ]);
+    ctx.send(
+        CreateReply::default()
+            .content(url)
+            .allowed_mentions(CreateAllowedMentions::new()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+/// Count the cards matching the given filters, broken down by type.
+///
+/// Takes the same name, effect and filter options as `/search`.
+#[allow(clippy::too_many_arguments)]
+async fn count(
+    ctx: Context<'_>,
+    #[description = "Card Name"]
+    #[autocomplete = autocomplete_search]
+    name: Option<String>,
+    #[description = "Card Effect"] effect: Option<String>,
+    #[description = "Card Type"] card_type: Option<CardType>,
+    #[description = "Card Frame"] frame_type: Option<FrameType>,
+    #[description = "Monster Attribute"] attribute: Option<Attribute>,
+    #[description = "Monster Level, or several separated by commas"] level: Option<String>,
+    #[description = "Minimum Level"] min_level: Option<u32>,
+    #[description = "Maximum Level"] max_level: Option<u32>,
+    #[description = "Link Rating"] linkval: Option<u32>,
+    #[description = "Passcode range, such as 10000000-20000000, with either end optional"]
+    passcode: Option<String>,
+    #[description = "Minimum ATK"] min_atk: Option<i32>,
+    #[description = "Maximum ATK"] max_atk: Option<i32>,
+    #[description = "Minimum DEF"] min_def: Option<i32>,
+    #[description = "Maximum DEF"] max_def: Option<i32>,
+    #[description = "How monsters with ? ATK/DEF are treated"] unknown_stats: Option<UnknownStats>,
+    #[description = "Card Archetype, or \"none\" for cards without one"]
+    #[autocomplete = autocomplete_archetype]
+    archetype: Option<String>,
+    #[description = "Monster type, or spell/trap kind such as Counter"]
+    #[autocomplete = autocomplete_race]
+    race: Option<String>,
+    #[description = "Full type line, such as Continuous Spell or Tuner"]
+    #[autocomplete = autocomplete_full_type]
+    full_type: Option<String>,
+    #[description = "Card Category"]
+    #[autocomplete = autocomplete_category]
+    category: Option<String>,
+    #[description = "Only cards without an attribute"] no_attribute: Option<bool>,
+    #[description = "How name and effect are matched, e.g. as a regex over the card text"]
+    match_mode: Option<MatchMode>,
+    #[description = "Only show the count to you"] ephemeral: Option<bool>,
+) -> Result<(), anyhow::Error> {
+    let filter = FilterArgs {
+        card_type,
+        frame_type,
+        attribute,
+        level,
+        min_level,
+        max_level,
+        linkval,
+        passcode,
+        min_atk,
+        max_atk,
+        min_def,
+        max_def,
+        unknown_stats,
+        archetype,
+        race,
+        full_type,
+        category,
+        no_attribute,
+        match_mode,
+    }
+    .filter()?;
+    let counts = ctx
+        .data()
+        .count_cards(name.as_deref(), effect.as_deref(), &filter)
+        .await?;
+    let total: usize = counts.values().sum();
+    let mut breakdown: Vec<_> = counts.into_iter().collect();
+    breakdown.sort_by(|(a_ty, a), (b_ty, b)| b.cmp(a).then(a_ty.cmp(b_ty)));
+    let breakdown = breakdown
+        .iter()
+        .map(|(ty, count)| format!("{count} {ty}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let plural = if total == 1 { "" } else { "es" };
+    let content = if total == 0 {
+        "0 matches".to_string()
+    } else {
+        format!("{total} match{plural}: {breakdown}")
+    };
+    ctx.send(
+        CreateReply::default()
+            .content(content)
+            .ephemeral(ephemeral.unwrap_or_default()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, poise::ChoiceParameter)]
+enum TextLength {
+    #[default]
+    Characters,
+    Words,
+}
+
+impl TextLength {
+    fn measure(self, text: &str) -> usize {
+        match self {
+            TextLength::Characters => text.chars().count(),
+            TextLength::Words => text.split_whitespace().count(),
+        }
+    }
+
+    fn unit(self) -> &'static str {
+        match self {
+            TextLength::Characters => "characters",
+            TextLength::Words => "words",
+        }
+    }
+}
+
+/// The `count` cards with the longest descriptions, longest first.
+fn longest_texts<'a>(
+    cards: impl Iterator<Item = &'a CardDatum>,
+    by: TextLength,
+    count: usize,
+) -> Vec<(usize, &'a CardDatum)> {
+    let mut lengths: Vec<_> = cards.map(|card| (by.measure(&card.desc), card)).collect();
+    lengths.sort_by(|(a, a_card), (b, b_card)| b.cmp(a).then(a_card.name.cmp(&b_card.name)));
+    lengths.truncate(count);
+    lengths
+}
+
+#[poise::command(slash_command)]
+/// List the cards with the longest card text.
+async fn wordiest(
+    ctx: Context<'_>,
+    #[description = "How many cards to list (at most 25)"]
+    #[min = 1]
+    #[max = 25]
+    count: Option<usize>,
+    #[description = "Measure the text in characters or words"] by: Option<TextLength>,
+) -> Result<(), anyhow::Error> {
+    let by = by.unwrap_or_default();
+    let description = {
+        let cards = ctx.data().cards.get().await;
+        longest_texts(cards.iter(), by, count.unwrap_or(10).min(PAGE_SIZE))
+            .iter()
+            .enumerate()
+            .map(|(i, (length, card))| format!("{}. {} ({length} {})", i + 1, card.name, by.unit()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    let embed = serenity::CreateEmbed::new()
+        .title("Wordiest cards")
+        .description(description);
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// Render counts as "key: count" lines, most common first.
+fn breakdown<K: std::fmt::Display + Ord>(counts: BTreeMap<K, usize>) -> String {
+    // Discord rejects empty field values.
+    if counts.is_empty() {
+        return "None".to_string();
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_by(|(a_key, a), (b_key, b)| b.cmp(a).then(a_key.cmp(b_key)));
+    counts
+        .iter()
+        .map(|(key, count)| format!("{key}: {count}"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[poise::command(slash_command)]
+/// Summarize the makeup of the card pool.
+async fn stats(ctx: Context<'_>) -> Result<(), anyhow::Error> {
+    // Custom cards aren't in the card data, only on the allowed list.
+    let customs = ctx.data().allowed_customs.get().await.len();
+    let customs = match ctx.data().allowed_customs.last_success() {
+        Some(_) => format!(", plus **{customs}** allowed custom cards"),
+        None => ". The allowed custom cards could not be loaded".to_string(),
+    };
+    let embed = {
+        let cards = ctx.data().cards.get().await;
+        let mut frames = BTreeMap::new();
+        let mut attributes = BTreeMap::new();
+        let mut levels = BTreeMap::new();
+        let mut archetypes = BTreeSet::new();
+        let mut in_archetype = 0;
+        for card in cards.iter() {
+            *frames.entry(card.frame_type.as_str()).or_default() += 1;
+            if let Some(attribute) = card.attribute.as_deref().filter(|a| !a.is_empty()) {
+                *attributes.entry(attribute).or_default() += 1;
+            }
+            if let Some(level) = card.level {
+                *levels.entry(format!("Lv {level}")).or_default() += 1;
+            }
+            if !card.archetype.is_empty() {
+                archetypes.insert(card.archetype.as_str());
+                in_archetype += 1;
+            }
+        }
+        serenity::CreateEmbed::new()
+            .title("Card pool stats")
+            .description(format!(
+                "**{}** cards, **{in_archetype}** of them in one of **{}** archetypes{customs}",
+                cards.iter().len(),
+                archetypes.len()
+            ))
+            .field("Frame", breakdown(frames), true)
+            .field("Attribute", breakdown(attributes), true)
+            .field("Level", breakdown(levels), true)
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "Data as of {}",
+                format_age(ctx.data().cards.last_success().unwrap_or_default())
+            )))
+    };
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+/// How an archetype's cards are spread across monster levels, plus its spell
+/// and trap counts.
+#[derive(Debug, Default)]
+struct LevelCurve {
+    levels: BTreeMap<u32, usize>,
+    /// Monsters without a level, such as Link monsters.
+    unleveled: usize,
+    spells: usize,
+    traps: usize,
+}
+
+impl LevelCurve {
+    fn new<'a>(cards: impl IntoIterator<Item = &'a CardDatum>) -> LevelCurve {
+        let mut curve = LevelCurve::default();
+        for card in cards {
+            if CardType::Spell.matches(card) {
+                curve.spells += 1;
+            } else if CardType::Trap.matches(card) {
+                curve.traps += 1;
+            } else if let Some(level) = card.level {
+                *curve.levels.entry(level).or_default() += 1;
+            } else {
+                curve.unleveled += 1;
+            }
+        }
+        curve
+    }
+
+    /// A text bar chart; bars are scaled so the longest is 20 blocks wide.
+    fn render(&self) -> String {
+        let mut rows: Vec<(String, usize)> = self
+            .levels
+            .iter()
+            .map(|(level, count)| (format!("Lv {level:>2}"), *count))
+            .collect();
+        if self.unleveled > 0 {
+            rows.push(("No Lv".to_string(), self.unleveled));
+        }
+        let mut lines = vec![];
+        if rows.is_empty() {
+            lines.push("This archetype has no monsters.".to_string());
+        } else {
+            let max = rows.iter().map(|(_, count)| *count).max().unwrap_or(1);
+            lines.push("```".to_string());
+            for (label, count) in rows {
+                let width = (count * 20).div_ceil(max);
+                lines.push(format!("{label:<5} {} {count}", "█".repeat(width)));
+            }
+            lines.push("```".to_string());
+        }
+        lines.push(format!("Spells: {} | Traps: {}", self.spells, self.traps));
+        lines.join("\n")
+    }
+}
+
+#[poise::command(slash_command)]
+/// Show the monster level curve of an archetype.
+async fn curve(
+    ctx: Context<'_>,
+    #[description = "Card Archetype"]
+    #[autocomplete = autocomplete_archetype]
+    archetype: String,
+) -> Result<(), anyhow::Error> {
+    let embed = {
+        let cards = ctx.data().cards.get().await;
+        let members: Vec<_> = cards
+            .iter()
+            .filter(|c| c.archetype.eq_ignore_ascii_case(&archetype))
+            .collect();
+        if members.is_empty() {
+            empty_archetype_embed(&archetype)
+        } else {
+            serenity::CreateEmbed::new()
+                .title(format!("{archetype} level curve"))
+                .description(LevelCurve::new(members).render())
+        }
+    };
+    ctx.send(CreateReply::default().embed(embed)).await?;
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+/// List every card in an archetype. Use "none" for cards without one.
+async fn archetype(
+    ctx: Context<'_>,
+    #[description = "Card Archetype"]
+    #[autocomplete = autocomplete_archetype]
+    archetype: String,
+) -> Result<(), anyhow::Error> {
+    let members: Vec<CardDatum> = {
+        let cards = ctx.data().cards.get().await;
+        let wanted = if archetype.eq_ignore_ascii_case("none") {
+            ""
+        } else {
+            &archetype
+        };
+        cards
+            .iter()
+            .filter(|c| c.archetype.eq_ignore_ascii_case(wanted))
+            .cloned()
+            .collect()
+    };
+
+    if members.is_empty() {
+        ctx.send(CreateReply::default().embed(empty_archetype_embed(&archetype)))
+            .await?;
+        return Ok(());
+    }
+
+    let mut grouped: BTreeMap<ArchetypeSection, Vec<&CardDatum>> = BTreeMap::new();
+    for card in &members {
+        grouped
+            .entry(ArchetypeSection::of(card))
+            .or_default()
+            .push(card);
+    }
+    let mut lines = Vec::new();
+    for (section, mut cards) in grouped {
+        cards.sort_by(|a, b| a.name.cmp(&b.name));
+        lines.push(format!("**{} ({})**", section.title(), cards.len()));
+        lines.extend(
+            cards
+                .iter()
+                .map(|card| format!("- {}", card.archetype_entry())),
+        );
+    }
+
+    let pages = lines.len().div_ceil(PAGE_SIZE);
+    paginate(ctx, pages, false, &[], false, |page| {
+        serenity::CreateEmbed::new()
+            .title(format!("{archetype} ({} cards)", members.len()))
+            .description(
+                lines
+                    .iter()
+                    .skip(page * PAGE_SIZE)
+                    .take(PAGE_SIZE)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            )
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "Page {}/{pages}",
+                page + 1
+            )))
+    })
+    .await
+}
+
+/// The groups `/archetype` lists an archetype's cards in, in listing order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ArchetypeSection {
+    MainDeckMonsters,
+    Fusion,
+    Synchro,
+    Xyz,
+    Link,
+    Spells,
+    Traps,
+}
+
+impl ArchetypeSection {
+    fn of(card: &CardDatum) -> ArchetypeSection {
+        let frame = card.frame_type.as_str();
+        if frame == "spell" {
+            ArchetypeSection::Spells
+        } else if frame == "trap" {
+            ArchetypeSection::Traps
+        } else if frame.starts_with("fusion") {
+            ArchetypeSection::Fusion
+        } else if frame.starts_with("synchro") {
+            ArchetypeSection::Synchro
+        } else if frame.starts_with("xyz") {
+            ArchetypeSection::Xyz
+        } else if frame == "link" {
+            ArchetypeSection::Link
+        } else {
+            ArchetypeSection::MainDeckMonsters
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            ArchetypeSection::MainDeckMonsters => "Main Deck Monsters",
+            ArchetypeSection::Fusion => "Fusion Monsters",
+            ArchetypeSection::Synchro => "Synchro Monsters",
+            ArchetypeSection::Xyz => "Xyz Monsters",
+            ArchetypeSection::Link => "Link Monsters",
+            ArchetypeSection::Spells => "Spells",
+            ArchetypeSection::Traps => "Traps",
+        }
+    }
+}
+
+/// The most cards `/shared_support` will list.
+const SHARED_SUPPORT_LIMIT: usize = 50;
+
+/// The cards supporting both archetypes `a` and `b`, see `shared_support`,
+/// in name order.
+fn shared_support_cards<'a>(cards: &'a CardIndex, a: &str, b: &str) -> Vec<&'a CardDatum> {
+    let a_term = normalize_search_term(a);
+    let b_term = normalize_search_term(b);
+    let mut shared: Vec<&CardDatum> = cards
+        .entries()
+        .filter(|(card, _, desc)| {
+            let supports_a = card.archetype.eq_ignore_ascii_case(a) || desc.contains(&a_term);
+            let supports_b = card.archetype.eq_ignore_ascii_case(b) || desc.contains(&b_term);
+            supports_a && supports_b
+        })
+        .map(|(card, _, _)| card)
+        .collect();
+    shared.sort_by(|x, y| x.name.cmp(&y.name));
+    shared
+}
+
+#[poise::command(slash_command)]
+/// Find cards that support two archetypes at once.
+///
+/// A card counts if its text mentions both archetypes, or if it belongs to
+/// one of them and mentions the other.
+async fn shared_support(
+    ctx: Context<'_>,
+    #[description = "First archetype"]
+    #[autocomplete = autocomplete_archetype]
+    a: String,
+    #[description = "Second archetype"]
+    #[autocomplete = autocomplete_archetype]
+    b: String,
+) -> Result<(), anyhow::Error> {
+    let (shared, total) = {
+        let cards = ctx.data().cards.get().await;
+        let shared = shared_support_cards(&cards, &a, &b);
+        let total = shared.len();
+        let shown: Vec<CardDatum> = shared
+            .into_iter()
+            .take(SHARED_SUPPORT_LIMIT)
+            .cloned()
+            .collect();
+        (shown, total)
+    };
+
+    if shared.is_empty() {
+        let embed = serenity::CreateEmbed::new()
+            .title("No cards found".to_string())
+            .description(format!("No cards support both {a} and {b}"));
+        ctx.send(CreateReply::default().embed(embed)).await?;
+        return Ok(());
+    }
+
+    let pages = page_count(&shared);
+    let shown = if total > shared.len() {
+        format!(", showing {}", shared.len())
+    } else {
+        String::new()
+    };
+    paginate(ctx, pages, false, &[], false, |page| {
+        serenity::CreateEmbed::new()
+            .title(format!("{a} × {b} ({total} cards{shown})"))
+            .description(format!(
+                "- {}",
+                shared
+                    .iter()
+                    .skip(page * PAGE_SIZE)
+                    .take(PAGE_SIZE)
+                    .map(|card| card.list_entry())
+                    .collect::<Vec<_>>()
+                    .join("\n- ")
+            ))
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "Page {}/{pages}",
+                page + 1
+            )))
+    })
+    .await
+}
+
+#[poise::command(slash_command)]
+/// Show the current TPP banlist.
+async fn banlist(ctx: Context<'_>) -> Result<(), anyhow::Error> {
+    let data = ctx.data();
+    // Copy the names out into their sections, to be sorted and paged.
+    let mut sections: [(&str, Vec<String>); 3] = [
+        ("Forbidden", Vec::new()),
+        ("Limited", Vec::new()),
+        ("Semi-Limited", Vec::new()),
+    ];
+    for (name, &limit) in data.banlist.get().await.iter() {
+        if let Some((_, names)) = sections.get_mut(usize::from(limit)) {
+            names.push(name.clone());
+        }
+    }
+    let Some(updated) = data.banlist.last_success() else {
+        return Err(anyhow!(
+            "The TPP banlist could not be loaded. Please try again later."
+        ));
+    };
+    let updated = format_age(updated);
+
+    let mut pages: Vec<(&str, &[String])> = Vec::new();
+    for (title, names) in &mut sections {
+        names.sort();
+        pages.extend(names.chunks(PAGE_SIZE).map(|chunk| (*title, chunk)));
+    }
+    if pages.is_empty() {
+        let embed = serenity::CreateEmbed::new()
+            .title("TPP Banlist")
+            .description("No cards are currently restricted")
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "Updated {updated}"
+            )));
+        ctx.send(CreateReply::default().embed(embed)).await?;
+        return Ok(());
+    }
+
+    paginate(ctx, pages.len(), false, &[], false, |page| {
+        let (title, names) = pages[page];
+        serenity::CreateEmbed::new()
+            .title(format!("TPP Banlist: {title}"))
+            .description(format!("- {}", names.join("\n- ")))
+            .footer(serenity::CreateEmbedFooter::new(format!(
+                "Page {}/{} • Updated {updated}",
+                page + 1,
+                pages.len()
+            )))
+    })
+    .await
+}
+
+/// Check `deck` against the TPP pool, the allowed customs, the banlist and
+/// the deck building rules, returning whether it is valid and the report.
+fn validate_deck(
+    cards: &CardIndex,
+    allowed_customs: &[AllowedCustom],
+    banlist: &Banlist,
+    deck: &Deck,
+) -> (bool, String) {
+    let valid_cards: HashSet<_> = cards.iter().map(|c| &c.name).cloned().collect();
+
+    fn track_invalids<'a>(
+        valid_cards: &HashSet<String>,
+        allowed_customs: &[AllowedCustom],
+        cards: &'a Vec<DeckCard>,
+    ) -> (HashMap<&'a DeckCard, usize>, usize) {
+        let mut invalids = HashMap::new();
+        let mut invalid_count = 0;
+        for card in cards {
+            let allowed = allowed_customs.iter().any(|custom| custom.allows(card));
+            if !valid_cards.contains(&card.name) && !allowed {
+                *invalids.entry(card).or_default() += 1;
+                invalid_count += 1;
+            }
+        }
+        (invalids, invalid_count)
+    }
+
+    let add_invalids = |name: &str,
+                        invalids: HashMap<&DeckCard, usize>,
+                        invalid_count: usize,
+                        msg: &mut Vec<String>| {
+        if !invalids.is_empty() {
+            msg.push(format!(
+                "## {name} deck includes {invalid_count} invalid cards:"
+            ));
+            let mut entries: Vec<_> = invalids.into_iter().collect();
+            entries.sort_by_key(|(c, _)| &c.name);
+            for (card, count) in entries {
+                let suggestion = match cards.closest(&card.name).first() {
+                    Some(closest) => format!(" (did you mean **{}**?)", closest.name),
+                    None => String::new(),
+                };
+                msg.push(format!("- {} x {count}{suggestion}", card.describe()));
+            }
+        }
+    };
+
+    /// One line per invalid card with its copies across the zones, such as
+    /// "**X** x 3 (Main 2, Side 1)", in name order.
+    fn invalid_totals(zones: &[(&str, &HashMap<&DeckCard, usize>)]) -> Vec<String> {
+        // Keyed by the description, which tells customs apart by author.
+        let mut totals: BTreeMap<String, (Vec<String>, usize)> = BTreeMap::new();
+        for (zone, invalids) in zones {
+            for (card, &count) in *invalids {
+                let (per_zone, total) = totals.entry(card.describe()).or_default();
+                per_zone.push(format!("{zone} {count}"));
+                *total += count;
+            }
+        }
+        totals
+            .into_iter()
+            .map(|(card, (per_zone, total))| {
+                format!("- {card} x {total} ({})", per_zone.join(", "))
+            })
+            .collect()
+    }
+
+    fn rule_violations(deck: &Deck) -> Vec<String> {
+        let mut violations = vec![];
+        if !(40..=60).contains(&deck.main.len()) {
+            violations.push(format!(
+                "Main deck has {} cards (must be 40 to 60)",
+                deck.main.len()
+            ));
+        }
+        if deck.extra.len() > 15 {
+            violations.push(format!(
+                "Extra deck has {} cards (must be at most 15)",
+                deck.extra.len()
+            ));
+        }
+        if deck.side.len() > 15 {
+            violations.push(format!(
+                "Side deck has {} cards (must be at most 15)",
+                deck.side.len()
+            ));
+        }
+
+        let mut copies: HashMap<&str, usize> = HashMap::new();
+        for card in deck.main.iter().chain(&deck.extra) {
+            *copies.entry(&card.name).or_default() += 1;
+        }
+        let mut over_limit: Vec<_> = copies.into_iter().filter(|(_, n)| *n > 3).collect();
+        over_limit.sort();
+        for (name, count) in over_limit {
+            violations.push(format!("**{name}** appears {count} times (at most 3)"));
+        }
+        violations
+    }
+
+    let (invalid_main, main_count) = track_invalids(&valid_cards, allowed_customs, &deck.main);
+    let (invalid_side, side_count) = track_invalids(&valid_cards, allowed_customs, &deck.side);
+    let (invalid_extra, extra_count) = track_invalids(&valid_cards, allowed_customs, &deck.extra);
+    let violations = rule_violations(deck);
+    let BanlistViolations {
+        forbidden,
+        over_limit,
+    } = BanlistViolations::new(banlist, deck);
+
+    let has_invalids =
+        !invalid_main.is_empty() || !invalid_side.is_empty() || !invalid_extra.is_empty();
+    let valid =
+        !has_invalids && violations.is_empty() && forbidden.is_empty() && over_limit.is_empty();
+    let msg = if valid {
+        "This deck is valid.".to_string()
+    } else {
+        let mut msg = vec![];
+        if has_invalids {
+            msg.push(format!(
+                "# This deck has the following {} invalid cards:",
+                main_count + side_count + extra_count
+            ));
+            msg.push("## Totals across the zones:".to_string());
+            msg.extend(invalid_totals(&[
+                ("Main", &invalid_main),
+                ("Side", &invalid_side),
+                ("Extra", &invalid_extra),
+            ]));
+            add_invalids("Main", invalid_main, main_count, &mut msg);
+            add_invalids("Side", invalid_side, side_count, &mut msg);
+            add_invalids("Extra", invalid_extra, extra_count, &mut msg);
+        }
+        if !forbidden.is_empty() {
+            msg.push("## Forbidden cards:".to_string());
+            for (name, count) in forbidden {
+                msg.push(format!("- **{name}** x {count}"));
+            }
+        }
+        if !over_limit.is_empty() {
+            msg.push("## Cards over their banlist limit:".to_string());
+            for (name, count, limit) in over_limit {
+                msg.push(format!("- **{name}** x {count} (limit {limit})"));
+            }
+        }
+        if !violations.is_empty() {
+            msg.push("## Rule violations:".to_string());
+            msg.extend(violations.into_iter().map(|v| format!("- {v}")));
+        }
+        msg.join("\n")
+    };
+    (valid, msg)
+}
+
+/// Why decks can't be checked, when the banlist or the allowed customs have
+/// never loaded. Without them every custom would be invalid and nothing
+/// forbidden, so any verdict would be wrong. Only meaningful after a
+/// `refreshed` of each has had the chance to retry the load.
+fn deck_sources_missing(data: &Data) -> Option<&'static str> {
+    if data.allowed_customs.last_success().is_none() {
+        Some(
+            "The list of allowed custom cards could not be loaded, so decks can't be \
+             checked right now.",
+        )
+    } else if data.banlist.last_success().is_none() {
+        Some("The TPP banlist could not be loaded, so decks can't be checked right now.")
+    } else {
+        None
+    }
+}
+
+#[poise::command(slash_command)]
+/// Verify that the provided deck is valid in the TPP format.
+async fn check_deck(
+    ctx: Context<'_>,
+    #[description = "DuelingBook URL (https://www.duelingbook.com/deck?id=<id>) or ydke:// code"]
+    url: Option<String>,
+    #[description = "A .ydk deck file"] file: Option<serenity::Attachment>,
+    #[description = "Post the result in the channel with a verdict reaction"] public: Option<bool>,
+) -> Result<(), anyhow::Error> {
+    let public = public.unwrap_or_default();
+    if public {
+        ctx.defer().await?;
+    } else {
+        ctx.defer_ephemeral().await?;
+    }
+
+    // Deferred already, so there is time to wait on a refresh, and a deck
+    // check is worth getting right.
+    let allowed_customs = ctx.data().allowed_customs.refreshed().await;
+    let banlist = ctx.data().banlist.refreshed().await;
+    if let Some(reason) = deck_sources_missing(ctx.data()) {
+        return Err(anyhow!("{reason} Please try again later."));
+    }
+    let data = ctx.data().cards.get().await;
+    let deck: Deck = match (url, file) {
+        (Some(_), Some(_)) => {
+            return Err(anyhow!(
+                "Please provide either a deck URL or a .ydk file, not both"
+            ))
+        }
+        (None, None) => return Err(anyhow!("Please provide a deck URL or a .ydk file")),
+        (Some(code), None) if ydke::YdkeDeck::is_ydke(&code) => {
+            let ydke = ydke::YdkeDeck::parse(&code)?;
+            Deck::from_passcodes(&data.passcode_names(), &ydke.main, &ydke.side, &ydke.extra)
+        }
+        (Some(url), None) => duelingbook::DuelingBookDeck::get_deck(url).await?.into(),
+        (None, Some(file)) => {
+            if file.size > 64 * 1024 {
+                return Err(anyhow!("That file is too large to be a .ydk deck"));
+            }
+            let text = String::from_utf8(file.download().await?)
+                .map_err(|_| anyhow!("That file is not a .ydk deck"))?;
+            let ydk = ydk::YdkDeck::parse(&text)?;
+            Deck::from_passcodes(&data.passcode_names(), &ydk.main, &ydk.side, &ydk.extra)
+        }
+    };
+
+    let (valid, msg) = validate_deck(&data, &allowed_customs, &banlist, &deck);
+    let previous = match (&ctx.data().submissions, deck.id) {
+        (Some(log), Some(id)) => log.lock().expect("submission log poisoned").record(
+            id,
+            Submission {
+                user: ctx.author().id,
+                at: Utc::now(),
+            },
+        ),
+        _ => None,
+    };
+    let msg = match previous {
+        Some(first) => format!(
+            "**Warning:** this deck was already submitted by <@{}> <t:{}:R>.\n{msg}",
+            first.user,
+            first.at.timestamp()
+        ),
+        None => msg,
+    };
+    let mut replies = Vec::new();
+    for reply in deck_report(&msg) {
+        replies.push(ctx.send(reply).await?);
+    }
+    // Ephemeral messages can't carry reactions.
+    if let (true, Some(reply)) = (public, replies.first()) {
+        let message = reply.message().await?;
+        if let Err(e) = message.react(ctx, verdict_emoji(valid)).await {
+            tracing::warn!("Could not add deck verdict reaction: {e}");
+        }
+    }
+    Ok(())
+}
+
+#[poise::command(slash_command)]
+/// Convert a DuelingBook deck to a ydke:// code for EDOPro, checking it for TPP too.
+async fn export_deck(
+    ctx: Context<'_>,
+    #[description = "Deck URL in the format https://www.duelingbook.com/deck?id=<id>"] url: String,
+) -> Result<(), anyhow::Error> {
+    ctx.defer_ephemeral().await?;
+
+    let deck: Deck = duelingbook::DuelingBookDeck::get_deck(url).await?.into();
+    // Deferred already, so there is time to wait on a refresh, and a deck
+    // check is worth getting right.
+    let allowed_customs = ctx.data().allowed_customs.refreshed().await;
+    let banlist = ctx.data().banlist.refreshed().await;
+    let data = ctx.data().cards.get().await;
+    let passcodes = data.name_passcodes();
+
+    let resolve = |cards: &[DeckCard]| -> Vec<u32> {
+        cards
+            .iter()
+            .filter_map(|card| passcodes.get(card.name.as_str()).copied())
+            .collect()
+    };
+    let ydke = ydke::YdkeDeck {
+        main: resolve(&deck.main),
+        extra: resolve(&deck.extra),
+        side: resolve(&deck.side),
+    };
+    let mut unexportable: Vec<_> = deck
+        .main
+        .iter()
+        .chain(&deck.extra)
+        .chain(&deck.side)
+        .filter(|card| !passcodes.contains_key(card.name.as_str()))
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut msg = vec![format!("```\n{}\n```", ydke.encode())];
+    // The code is still worth having when the deck can't be checked.
+    match deck_sources_missing(ctx.data()) {
+        Some(reason) => msg.push(format!("**Warning:** {reason}")),
+        None => msg.push(validate_deck(&data, &allowed_customs, &banlist, &deck).1),
+    }
+    if !unexportable.is_empty() {
+        unexportable.sort_by_key(|c| &c.name);
+        msg.push("## These cards have no passcode and were left out:".to_string());
+        for card in unexportable {
+            msg.push(format!("- {}", card.describe()));
+        }
+    }
+    for reply in deck_report(&msg.join("\n")) {
+        ctx.send(reply).await?;
+    }
+    Ok(())
+}
+
+/// The replies carrying a deck report, split to fit in messages. Card names
+/// and usernames come from users, so nothing in them may ping anyone.
+fn deck_report(report: &str) -> Vec<CreateReply> {
+    split_message(report)
+        .into_iter()
+        .enumerate()
+        .map(|(i, piece)| {
+            CreateReply::default()
+                .content(piece)
+                .reply(i == 0)
+                .allowed_mentions(CreateAllowedMentions::new())
+        })
+        .collect()
+}
+
+fn verdict_emoji(valid: bool) -> char {
+    if valid {
+        '✅'
+    } else {
+        '❌'
+    }
+}
+
+/// Cards in the pool that are missing data needed to display them properly,
+/// grouped by what is missing.
+#[derive(Debug, Default)]
+struct AuditReport<'a> {
+    empty_desc: Vec<&'a str>,
+    empty_full_type: Vec<&'a str>,
+    missing_art: Vec<&'a str>,
+    missing_stats: Vec<&'a str>,
+}
+
+impl<'a> AuditReport<'a> {
+    fn new(cards: impl IntoIterator<Item = &'a CardDatum>) -> AuditReport<'a> {
+        let mut report = AuditReport::default();
+        for card in cards {
+            if card.desc.trim().is_empty() {
+                report.empty_desc.push(&card.name);
+            }
+            if card.full_type.trim().is_empty() {
+                report.empty_full_type.push(&card.name);
+            }
+            if card.image_url.trim().is_empty() {
+                report.missing_art.push(&card.name);
+            }
+            if CardType::Monster.matches(card) && card.atk.is_none() && card.def.is_none() {
+                report.missing_stats.push(&card.name);
+            }
+        }
+        report
+    }
+
+    fn is_empty(&self) -> bool {
+        self.empty_desc.is_empty()
+            && self.empty_full_type.is_empty()
+            && self.missing_art.is_empty()
+            && self.missing_stats.is_empty()
+    }
+
+    fn render(&self) -> String {
+        let mut msg = vec![];
+        for (heading, names) in [
+            ("Empty description", &self.empty_desc),
+            ("Empty type line", &self.empty_full_type),
+            ("Missing art", &self.missing_art),
+            ("Monsters missing ATK and DEF", &self.missing_stats),
+        ] {
+            if !names.is_empty() {
+                msg.push(format!("## {heading} ({})", names.len()));
+                msg.extend(names.iter().map(|name| format!("- {name}")));
+            }
+        }
+        msg.join("\n")
+    }
+
+    /// The `/audit` reply, with the report attached as a file when it is too
+    /// long for a message.
+    fn reply(&self) -> CreateReply {
+        if self.is_empty() {
+            return CreateReply::default().content("No problems found.");
+        }
+        let msg = self.render();
+        if msg.chars().count() > MESSAGE_LIMIT {
+            CreateReply::default()
+                .content("The audit report is attached.")
+                .attachment(serenity::CreateAttachment::bytes(msg, "audit.md"))
+        } else {
+            CreateReply::default().content(msg)
+        }
+    }
+}
+
+#[poise::command(slash_command, owners_only)]
+/// Report cards in the pool that are missing display data.
+async fn audit(ctx: Context<'_>) -> Result<(), anyhow::Error> {
+    ctx.defer_ephemeral().await?;
+
+    let reply = AuditReport::new(ctx.data().cards.get().await.iter()).reply();
+    ctx.send(reply).await?;
+    Ok(())
+}
+
+/// The art URLs the embed for the card named `name` would try, in order, or
+/// the one built from the name alone if no card has it.
+fn image_check_candidates(cards: &CardIndex, name: &str) -> Vec<String> {
+    let candidates = cards
+        .iter()
+        .find(|card| card.name.eq_ignore_ascii_case(name))
+        .map(CardDatum::art_candidates)
+        .unwrap_or_default();
+    if candidates.is_empty() {
+        vec![image_url_for(name)]
+    } else {
+        candidates
+    }
+}
+
+/// Render the `/image_check` results as a Markdown table of name, image URL,
+/// and whether the URL could be fetched.
+fn image_check_table(rows: &[(&str, String, Result<bool, reqwest::Error>)]) -> String {
+    let mut table = "| Name | Image URL | Reachable |\n| --- | --- | --- |\n".to_string();
+    for (name, url, reachable) in rows {
+        let reachable = match reachable {
+            Ok(true) => "yes".to_string(),
+            Ok(false) => "no".to_string(),
+            Err(e) => format!("error: {e}"),
+        };
+        table.push_str(&format!("| {name} | {url} | {reachable} |\n"));
+    }
+    table
+}
+
+#[poise::command(slash_command)]
+/// Show how long the bot has been up and how fresh its card data is.
+async fn status(ctx: Context<'_>) -> Result<(), anyhow::Error> {
+    let data = ctx.data();
+    let card_count = data.cards.get().await.iter().count();
+    let uptime = format_duration(Utc::now() - data.started)
+        .unwrap_or_else(|| "less than a minute".to_string());
+    let next_refresh = match format_duration(data.cards.expires() - Utc::now()) {
+        Some(wait) => format!("in {wait}"),
+        None if data.cards.expires() > Utc::now() => "in less than a minute".to_string(),
+        None => "on the next search".to_string(),
+    };
+    let embed = serenity::CreateEmbed::new()
+        .title("Status")
+        .color(serenity::Color::DARK_GREY)
+        .field("Version", env!("CARGO_PKG_VERSION"), true)
+        .field("Uptime", uptime, true)
+        .field("Cards loaded", card_count.to_string(), true)
+        .field(
+            "Card data",
+            format!(
+                "Updated {}, next refresh {next_refresh}",
+                format_age(data.cards.last_success().unwrap_or_default())
+            ),
+            false,
+        );
+    ctx.send(CreateReply::default().embed(embed).ephemeral(true))
+        .await?;
+    Ok(())
+}
+
+/// Run `tasks` concurrently, but only as many at a time as `permits` has
+/// permits, returning their outputs in order. Bulk image features go through
+/// this so they share the `IMAGE_CHECK_CONCURRENCY` limit.
+async fn join_bounded<F: std::future::Future>(
+    permits: &tokio::sync::Semaphore,
+    tasks: impl IntoIterator<Item = F>,
+) -> Vec<F::Output> {
+    futures::future::join_all(tasks.into_iter().map(|task| async move {
+        let _permit = permits.acquire().await.expect("semaphore is never closed");
+        task.await
+    }))
+    .await
+}
+
+#[poise::command(slash_command, owners_only)]
+/// Check that the art for each of the given cards can be fetched.
+async fn image_check(
+    ctx: Context<'_>,
+    #[description = "Card names, separated by semicolons"] names: String,
+) -> Result<(), anyhow::Error> {
+    ctx.defer_ephemeral().await?;
+
+    let names: Vec<&str> = names
+        .split([';', '\n'])
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.is_empty() {
+        return Err(anyhow!("No card names were given"));
+    }
+
+    let index = ctx.data().cards.get().await;
+    let client = reqwest::Client::new();
+    let checks = names.iter().map(|&name| {
+        let client = &client;
+        let candidates = image_check_candidates(&index, name);
+        async move {
+            // Like the embed: the first URL that loads, otherwise the first
+            // one with how it failed.
+            let mut first = None;
+            for url in candidates {
+                let reachable = client
+                    .head(&url)
+                    .timeout(ART_CHECK_TIMEOUT)
+                    .send()
+                    .await
+                    .map(|response| response.status().is_success());
+                if matches!(reachable, Ok(true)) {
+                    return (name, url, reachable);
+                }
+                first.get_or_insert((name, url, reachable));
+            }
+            first.expect("there is always a candidate")
+        }
+    });
+    let rows = join_bounded(&ctx.data().image_checks, checks).await;
+
+    let unreachable = rows
+        .iter()
+        .filter(|(_, _, reachable)| !matches!(reachable, Ok(true)))
+        .count();
+    ctx.send(
+        CreateReply::default()
+            .content(format!(
+                "Checked {} images, {unreachable} unreachable.",
+                rows.len()
+            ))
+            .attachment(serenity::CreateAttachment::bytes(
+                image_check_table(&rows),
+                "image_check.md",
+            )),
+    )
+    .await?;
+    Ok(())
+}
+
+#[poise::command(slash_command, owners_only)]
+/// Time a fixed set of representative searches against the card pool.
+async fn bench(
+    ctx: Context<'_>,
+    #[description = "How many times to run each search"]
+    #[min = 1]
+    #[max = 1000]
+    iterations: Option<u32>,
+) -> Result<(), anyhow::Error> {
+    ctx.defer_ephemeral().await?;
+
+    let iterations = iterations.unwrap_or(20);
+    let monsters = CardFilter {
+        card_type: Some(CardType::Monster),
+        ..Default::default()
+    };
+    let queries = [
+        ("short name", Some("dark"), None, CardFilter::default()),
+        (
+            "long name",
+            Some("plunder patroll"),
+            None,
+            CardFilter::default(),
+        ),
+        (
+            "alternatives",
+            Some("dragon | magician"),
+            None,
+            CardFilter::default(),
+        ),
+        ("broad effect", None, Some("monster"), CardFilter::default()),
+        ("effect and type", None, Some("destroy * draw"), monsters),
+    ];
+
+    let data = ctx.data();
+    let mut timings = Vec::new();
+    for (label, name, effect, filter) in &queries {
+        // Warm up caches and the allocator before timing.
+        data.filter_cards(*name, *effect, filter).await?;
+        let start = Instant::now();
+        let mut matches = 0;
+        for _ in 0..iterations {
+            matches = data.filter_cards(*name, *effect, filter).await?.len();
+        }
+        timings.push((*label, start.elapsed(), matches));
+    }
+
+    ctx.send(CreateReply::default().content(bench_report(iterations, &timings)))
+        .await?;
+    Ok(())
+}
+
+/// Summarize `/bench` from the `(label, elapsed, matches)` of each search,
+/// where `elapsed` is the time all `iterations` runs of it took.
+fn bench_report(iterations: u32, timings: &[(&str, Duration, usize)]) -> String {
+    let mut lines = vec![format!("Timings over {iterations} runs each:")];
+    let mut total = Duration::ZERO;
+    for (label, elapsed, matches) in timings {
+        total += *elapsed;
+        lines.push(format!(
+            "- {label}: {:.2?} ({matches} matches)",
+            *elapsed / iterations
+        ));
+    }
+    lines.push(format!(
+        "**Average over all searches:** {:.2?}",
+        total / (iterations * timings.len() as u32).max(1)
+    ));
+    lines.join("\n")
+}
+
+#[poise::command(slash_command, owners_only)]
+/// Show the raw DuelingBook response for a deck.
+async fn deck_raw(
+    ctx: Context<'_>,
+    #[description = "Deck URL in the format https://www.duelingbook.com/deck?id=<id>"] url: String,
+) -> Result<(), anyhow::Error> {
+    ctx.defer_ephemeral().await?;
+
+    let text = duelingbook::DuelingBookDeck::get_deck_text(url).await?;
+    ctx.send(deck_raw_reply(text)?).await?;
+    Ok(())
+}
+
+/// Attach a DuelingBook response as `deck.json`, pretty-printed when it is
+/// JSON and as it came otherwise, so error pages can be read too.
+fn deck_raw_reply(text: String) -> Result<CreateReply, anyhow::Error> {
+    let body = match serde_json::from_str::<serde_json::Value>(&text) {
+        Ok(value) => serde_json::to_string_pretty(&value)?,
+        Err(_) => text,
+    };
+    Ok(CreateReply::default()
+        .content(format!("DuelingBook returned {} bytes.", body.len()))
+        .attachment(serenity::CreateAttachment::bytes(body, "deck.json")))
+}
+
+/// What would be registered with Discord for each command, for checking the
+/// command definitions without connecting. Fails on the limits Discord would
+/// otherwise reject the registration for.
+fn command_summary(
+    commands: &[poise::Command<Data, anyhow::Error>],
+) -> Result<String, anyhow::Error> {
+    let mut summary = Vec::new();
+    for command in commands {
+        if command.parameters.len() > 25 {
+            return Err(anyhow!(
+                "/{} has {} options, but Discord allows 25",
+                command.name,
+                command.parameters.len()
+            ));
+        }
+        let descriptions = std::iter::once((&command.name, &command.description)).chain(
+            command
+                .parameters
+                .iter()
+                .map(|param| (&param.name, &param.description)),
+        );
+        for (name, description) in descriptions {
+            if description
+                .as_ref()
+                .is_some_and(|d| d.chars().count() > 100)
+            {
+                return Err(anyhow!(
+                    "The description of `{name}` in /{} is over 100 characters",
+                    command.name
+                ));
+            }
+        }
+
+        let Some(definition) = command.create_as_slash_command() else {
+            summary.push(format!("{} (not a slash command)", command.name));
+            continue;
+        };
+        summary.push(format!(
+            "{}: {}",
+            command.name,
+            serde_json::to_string_pretty(&definition)?
+        ));
+    }
+    Ok(summary.join("\n"))
+}
+
+/// Load a source that the bot can run without, falling back to empty data
+/// that is refreshed on first use if it can't be fetched at startup. Until
+/// then its `last_success` is `None`, which commands relying on it check.
+async fn fresh_or_empty<D: Default>(what: &str, refresh: RefreshFn<D>) -> FreshData<D> {
+    let frequency = chrono::Duration::minutes(15);
+    match FreshData::new(frequency, refresh).await {
+        Ok(data) => data,
+        Err(e) => {
+            tracing::warn!("Starting without {what}: {e:#}");
+            FreshData::stale(frequency, refresh, D::default())
+        }
+    }
+}
+
+/// Read a setting from the environment, falling back to `default` when it is
+/// unset or unparseable.
+fn env_or<T: FromStr>(key: &str, default: T) -> T {
+    match std::env::var(key) {
+        Ok(value) => value.parse().unwrap_or_else(|_| {
+            tracing::warn!("Ignoring invalid value {value:?} for {key}");
+            default
+        }),
+        Err(_) => default,
+    }
+}
+
+/// Drop the commands turned off with `ENABLE_<COMMAND>=false`, so they are
+/// never registered. Everything is enabled by default.
+fn enabled_commands(
+    commands: Vec<poise::Command<Data, anyhow::Error>>,
+) -> Vec<poise::Command<Data, anyhow::Error>> {
+    let names: Vec<&str> = commands.iter().map(|c| c.name.as_str()).collect();
+    let (disabled, unknown) = command_flags(&names, std::env::vars());
+    let disabled: HashSet<String> = disabled.into_iter().map(str::to_string).collect();
+    for key in unknown {
+        tracing::warn!("Ignoring {key}, as there is no such command");
+    }
+    let (enabled, disabled): (Vec<_>, Vec<_>) = commands
+        .into_iter()
+        .partition(|command| !disabled.contains(&command.name));
+    let names = |commands: &[poise::Command<_, _>]| {
+        commands
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    tracing::info!("Enabled commands: {}", names(&enabled));
+    if !disabled.is_empty() {
+        tracing::info!("Disabled commands: {}", names(&disabled));
+    }
+    enabled
+}
+
+/// Which of the command `names` the environment `vars` turn off with
+/// `ENABLE_<NAME>=false`, along with the `ENABLE_` variables that name no
+/// command, which are most likely typos. A value other than `true` or
+/// `false` is ignored with a warning, leaving the command enabled.
+fn command_flags<'a>(
+    names: &[&'a str],
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> (Vec<&'a str>, Vec<String>) {
+    let mut disabled = Vec::new();
+    let mut unknown = Vec::new();
+    for (key, value) in vars {
+        let Some(name) = key.strip_prefix("ENABLE_") else {
+            continue;
+        };
+        let Some(name) = names.iter().find(|n| n.eq_ignore_ascii_case(name)) else {
+            unknown.push(key);
+            continue;
+        };
+        match value.parse::<bool>() {
+            Ok(true) => {}
+            Ok(false) => disabled.push(*name),
+            Err(_) => tracing::warn!("Ignoring invalid value {value:?} for {key}"),
+        }
+    }
+    disabled.sort_unstable();
+    unknown.sort_unstable();
+    (disabled, unknown)
+}
+
+fn setup_tracing() -> Result<(), anyhow::Error> {
+    let appender = tracing_appender::rolling::RollingFileAppender::builder()
+        .max_log_files(10)
+        .filename_prefix("rolling")
+        .filename_suffix("log")
+        .rotation(tracing_appender::rolling::Rotation::DAILY)
+        .build("logs")?;
+
+    let subscriber = Registry::default()
+        .with(
+            // Stdout
+            tracing_subscriber::fmt::layer()
+                .compact()
+                .with_ansi(true)
+                .with_filter(tracing::level_filters::LevelFilter::from_level(
+                    tracing::Level::INFO,
+                )),
+        )
+        .with(
+            // Rolling logs
+            tracing_subscriber::fmt::layer()
+                .json()
+                .with_writer(appender)
+                .with_filter(
+                    tracing_subscriber::filter::Targets::new()
+                        .with_target("tpp-bot-beard", tracing::Level::TRACE)
+                        .with_default(tracing::Level::DEBUG),
+                ),
+        );
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<(), anyhow::Error> {
+    setup_tracing()?;
+    match dotenv::dotenv() {
+        Ok(_) => {}
+        // Everything can come from the real environment instead, as in CI.
+        Err(e) if e.not_found() => tracing::info!("No .env file found"),
+        Err(e) => return Err(e.into()),
+    }
+
+    let commands = enabled_commands(vec![
+        search(),
+        query_search(),
+        random(),
+        cotd(),
+        compare(),
+        text(),
+        count(),
+        link(),
+        wordiest(),
+        stats(),
+        curve(),
+        archetype(),
+        shared_support(),
+        banlist(),
+        check_deck(),
+        export_deck(),
+        deck_raw(),
+        status(),
+        image_check(),
+        bench(),
+        audit(),
+    ]);
+    if env_or("DRY_RUN", false) {
+        println!("{}", command_summary(&commands)?);
+        return Ok(());
+    }
+
+    let token = std::env::var("DISCORD_TOKEN")?;
+    let intents =
+        serenity::GatewayIntents::non_privileged() | serenity::GatewayIntents::MESSAGE_CONTENT;
+
+    let framework = poise::Framework::builder()
+        .options(poise::FrameworkOptions {
+            commands,
+            event_handler: |ctx, event, framework, data| {
+                Box::pin(event_handler(ctx, event, framework, data))
+            },
+            on_error: |error| Box::pin(on_error(error)),
+
+            ..Default::default()
+        })
+        .setup(|ctx, _ready, framework| {
+            Box::pin(async move {
+                poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                let autocomplete_cache = AutocompleteCache::new(
+                    Duration::from_secs(env_or("AUTOCOMPLETE_CACHE_TTL_SECS", 30)),
+                    env_or("AUTOCOMPLETE_CACHE_SIZE", 256),
+                );
+                let refresh_banlist: RefreshFn<Banlist> = |validators| {
+                    Box::pin(async move {
+                        let url = std::env::var("BANLIST_URL")
+                            .unwrap_or_else(|_| BANLIST_DATA.to_string());
+                        fetch_json(&url, validators)
+                            .await
+                            .context("Could not fetch banlist")
+                    })
+                };
+                let refresh_allowed_customs: RefreshFn<Vec<AllowedCustom>> = |validators| {
+                    Box::pin(async move {
+                        let url = std::env::var("ALLOWED_CUSTOMS_URL")
+                            .unwrap_or_else(|_| ALLOWED_CUSTOMS_DATA.to_string());
+                        fetch_json(&url, validators)
+                            .await
+                            .context("Could not fetch allowed custom cards")
+                    })
+                };
+                Ok(Data {
+                    cards: FreshData::with_retries(
+                        chrono::Duration::minutes(15),
+                        |validators| {
+                            Box::pin(async move {
+                                let refresh = fetch_json(CARD_DATA, validators)
+                                    .await
+                                    .context("Could not fetch new card data")?;
+                                Ok(refresh.map(CardIndex::new))
+                            })
+                        },
+                        env_or("STARTUP_ATTEMPTS", 5).max(1),
+                        Duration::from_secs(1),
+                    )
+                    .await?,
+                    banlist: fresh_or_empty("banlist", refresh_banlist).await,
+                    allowed_customs: fresh_or_empty(
+                        "allowed custom cards",
+                        refresh_allowed_customs,
+                    )
+                    .await,
+                    annotations: fresh_or_empty("annotations", |validators| {
+                        Box::pin(async move {
+                            let path = std::env::var("ANNOTATIONS_FILE")
+                                .unwrap_or_else(|_| "annotations.json".to_string());
+                            read_json_file(&path, validators)
+                                .await
+                                .context("Could not load annotations")
+                        })
+                    })
+                    .await,
+                    autocomplete_cache: Mutex::new(autocomplete_cache),
+                    submissions: env_or("TRACK_DECK_SUBMISSIONS", false)
+                        .then(|| Mutex::new(SubmissionLog::default())),
+                    image_checks: tokio::sync::Semaphore::new(
+                        env_or("IMAGE_CHECK_CONCURRENCY", 8)
+                            .clamp(1, tokio::sync::Semaphore::MAX_PERMITS),
+                    ),
+                    art_reachability: Mutex::new(HashMap::new()),
+                    started: Utc::now(),
+                })
+            })
+        })
+        .build();
+    let mut client = serenity::ClientBuilder::new(token, intents)
+        .framework(framework)
+        .await?;
+    client.start().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A card named `name` with the fields in `fields` set over blank ones.
+    fn card(name: &str, fields: serde_json::Value) -> CardDatum {
+        let mut value = serde_json::json!({
+            "name": name,
+            "full_type": "",
+            "race": "",
+            "desc": "",
+            "frameType": "",
+            "archetype": "",
+            "image_url": "",
+            "type": "",
+        });
+        if let serde_json::Value::Object(fields) = fields {
+            value
+                .as_object_mut()
+                .expect("the card is an object")
+                .extend(fields);
+        }
+        serde_json::from_value(value).expect("the test card is valid")
+    }
+
+    fn names<'a>(cards: &[&'a CardDatum]) -> Vec<&'a str> {
+        cards.iter().map(|card| card.name.as_str()).collect()
+    }
+
+    /// The names of the cards a `/search` for `name` lists, in order.
+    fn searched(
+        cards: &CardIndex,
+        name: &str,
+        filter: &CardFilter,
+        sort: SortOrder,
+    ) -> Vec<String> {
+        let (name_terms, effect_terms) = filter.queries(Some(name), None).unwrap();
+        cards
+            .search(Some(name), &name_terms, &effect_terms, filter, sort)
+            .into_iter()
+            .map(|card| card.name.clone())
+            .collect()
+    }
+
+    /// The names of the cards in `cards` that pass `filter`, in pool order.
+    fn passing<'a>(cards: &'a CardIndex, filter: &CardFilter) -> Vec<&'a str> {
+        cards
+            .iter()
+            .filter(|card| filter.apply(card))
+            .map(|card| card.name.as_str())
+            .collect()
+    }
+
+    fn pool() -> CardIndex {
+        CardIndex::new(
+            [
+                "Dark Magician",
+                "Dark Magician Girl",
+                "Magician of Dark Illusion",
+                "Blue-Eyes White Dragon",
+                "Red-Eyes Black Dragon",
+                "Plunder Patrollship Brann",
+            ]
+            .into_iter()
+            .map(|name| card(name, serde_json::json!({})))
+            .collect(),
+        )
+    }
+
+    #[test]
+    fn suggests_names_for_typos() {
+        let index = pool();
+        let suggest = |term| names(&index.suggest(term, 3, |_| true));
+        assert_eq!(suggest("dark magican")[0], "Dark Magician");
+        assert_eq!(suggest("drak magician")[0], "Dark Magician");
+        assert_eq!(
+            suggest("blue eyes whtie dragon")[0],
+            "Blue-Eyes White Dragon"
+        );
+    }
+
+    #[test]
+    fn suggests_names_for_missing_spaces_and_partial_words() {
+        let index = pool();
+        let suggest = |term| names(&index.suggest(term, 3, |_| true));
+        assert_eq!(suggest("darkmagician")[0], "Dark Magician");
+        assert_eq!(suggest("redeyes")[0], "Red-Eyes Black Dragon");
+        assert_eq!(suggest("patroll"), ["Plunder Patrollship Brann"]);
+    }
+
+    #[test]
+    fn suggests_from_a_full_sized_pool() {
+        const WORDS: [&str; 10] = [
+            "Ash", "Blossom", "Dragon", "Knight", "Spring", "Warrior", "Sky", "Striker", "Fairy",
+            "Tempest",
+        ];
+        let mut cards: Vec<_> = (0..10_000)
+            .map(|i| {
+                let name = format!("{} {} {}", WORDS[i % 10], WORDS[i / 10 % 10], i / 100);
+                card(&name, serde_json::json!({}))
+            })
+            .collect();
+        cards.push(card("Dark Magician", serde_json::json!({})));
+        let index = CardIndex::new(cards);
+
+        // The same ranking, tallied over every card instead of the trigram
+        // postings.
+        let brute_force = |term: &str| {
+            let grams = name_trigrams(&normalize_search_term(term));
+            let mut scored: Vec<_> = index
+                .entries()
+                .map(|(card, name, _)| {
+                    let own = name_trigrams(name);
+                    (own.intersection(&grams).count(), own.len(), &card.name)
+                })
+                .filter(|(shared, _, _)| shared * 2 >= grams.len())
+                .collect();
+            scored.sort_by_key(|&(shared, len, name)| (std::cmp::Reverse(shared), len, name));
+            scored
+                .into_iter()
+                .take(5)
+                .map(|(_, _, name)| name.clone())
+                .collect::<Vec<_>>()
+        };
+
+        let start = Instant::now();
+        for term in [
+            "dark magican",
+            "skystriker 42",
+            "tempest warior",
+            "fairy spring 7",
+        ] {
+            assert_eq!(
+                names(&index.suggest(term, 5, |_| true)),
+                brute_force(term),
+                "{term}"
+            );
+        }
+        assert_eq!(
+            names(&index.suggest("dark magican", 1, |_| true)),
+            ["Dark Magician"]
+        );
+        // Generous enough for a debug build on a slow machine, while still
+        // catching a suggestion path that has gone quadratic.
+        let per_search = start.elapsed() / 5;
+        assert!(per_search < Duration::from_millis(200), "{per_search:?}");
+    }
+
+    #[test]
+    fn suggests_only_cards_passing_the_filter() {
+        let index = CardIndex::new(vec![
+            card("Dark Magician", serde_json::json!({"level": 7})),
+            card("Dark Magician Girl", serde_json::json!({"level": 6})),
+        ]);
+        let filter = CardFilter {
+            levels: vec![6],
+            ..Default::default()
+        };
+        assert_eq!(
+            names(&index.suggest("dark magican", 1, |card| filter.apply(card))),
+            ["Dark Magician Girl"]
+        );
+    }
+
+    #[test]
+    fn suggests_nothing_for_short_or_unrelated_terms() {
+        let index = pool();
+        assert!(index.suggest("dm", 3, |_| true).is_empty());
+        assert!(index.suggest("zzzzqqq", 3, |_| true).is_empty());
+        assert_eq!(index.suggest("magician", 2, |_| true).len(), 2);
+    }
+
+    #[test]
+    fn finds_the_closest_names() {
+        let index = pool();
+        let closest = |term| names(&index.closest(term));
+        assert_eq!(
+            closest("Dark Magicain"),
+            ["Dark Magician", "Dark Magician Girl"]
+        );
+        assert_eq!(closest("plunder patrol"), ["Plunder Patrollship Brann"]);
+        assert_eq!(closest("Red Eyes Black Dragon"), ["Red-Eyes Black Dragon"]);
+        assert!(closest("Drk").is_empty());
+        assert!(closest("Elemental HERO Neos").is_empty());
+    }
+
+    #[test]
+    fn autocomplete_cache_is_dropped_on_refresh() {
+        let mut cache = AutocompleteCache::new(Duration::from_secs(60), 8);
+        cache.insert(1, "dark".to_string(), vec!["Dark Magician".to_string()]);
+        assert_eq!(
+            cache.get(1, "dark"),
+            Some(vec!["Dark Magician".to_string()])
+        );
+        assert_eq!(cache.get(1, "blue"), None);
+        assert_eq!(cache.get(2, "dark"), None);
+    }
+
+    #[test]
+    fn autocomplete_cache_expires_and_evicts() {
+        let mut cache = AutocompleteCache::new(Duration::ZERO, 8);
+        cache.insert(0, "dark".to_string(), Vec::new());
+        assert_eq!(cache.get(0, "dark"), None);
+
+        let mut cache = AutocompleteCache::new(Duration::from_secs(60), 2);
+        for partial in ["a", "b", "c"] {
+            cache.insert(0, partial.to_string(), vec![partial.to_string()]);
+            // Keep the insertion times apart, so "a" is the oldest.
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(cache.entries.len(), 2);
+        assert_eq!(cache.get(0, "a"), None);
+        assert_eq!(cache.get(0, "c"), Some(vec!["c".to_string()]));
+
+        let mut cache = AutocompleteCache::new(Duration::from_secs(60), 0);
+        cache.insert(0, "a".to_string(), Vec::new());
+        assert_eq!(cache.get(0, "a"), None);
+    }
+
+    #[test]
+    fn keeps_the_first_submission_of_each_deck() {
+        let submission = |user: u64, minutes: i64| Submission {
+            user: serenity::UserId::new(user),
+            at: chrono::DateTime::UNIX_EPOCH + chrono::Duration::minutes(minutes),
+        };
+        let mut log = SubmissionLog::default();
+        assert!(log.record(7, submission(1, 0)).is_none());
+        assert!(log.record(8, submission(2, 1)).is_none());
+
+        // Later submissions, even by the same user, are reported against the
+        // first and don't replace it.
+        for later in [submission(3, 2), submission(1, 3)] {
+            let first = log.record(7, later).unwrap();
+            assert_eq!(first.user, serenity::UserId::new(1));
+            assert_eq!(first.at, submission(1, 0).at);
+        }
+        assert_eq!(
+            log.record(8, submission(1, 4)).unwrap().user,
+            serenity::UserId::new(2)
+        );
+        assert_eq!(log.first.len(), 2);
+    }
+
+    fn deck(main: &[&str], extra: &[&str], side: &[&str]) -> Deck {
+        let cards = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| DeckCard {
+                    id: None,
+                    name: name.to_string(),
+                    username: None,
+                })
+                .collect()
+        };
+        Deck {
+            id: None,
+            main: cards(main),
+            side: cards(side),
+            extra: cards(extra),
+        }
+    }
+
+    #[test]
+    fn finds_banlist_violations_across_zones() {
+        let banlist = Banlist::from([
+            ("Pot of Greed".to_string(), 0),
+            ("Raigeki".to_string(), 1),
+            ("Ash Blossom".to_string(), 2),
+            ("Borreload Dragon".to_string(), 1),
+        ]);
+        let deck = deck(
+            &[
+                "Pot of Greed",
+                "Raigeki",
+                "Ash Blossom",
+                "Ash Blossom",
+                "Dark Magician",
+            ],
+            &["Borreload Dragon"],
+            &[
+                "Raigeki",
+                "Borreload Dragon",
+                "Dark Magician",
+                "Dark Magician",
+            ],
+        );
+        let BanlistViolations {
+            forbidden,
+            over_limit,
+        } = BanlistViolations::new(&banlist, &deck);
+        assert_eq!(forbidden, [("Pot of Greed", 1)]);
+        assert_eq!(over_limit, [("Borreload Dragon", 2, 1), ("Raigeki", 2, 1)]);
+    }
+
+    #[test]
+    fn validate_deck_reports_cards_outside_the_pool() {
+        let names: Vec<String> = (1..=14).map(|i| format!("Filler {i}")).collect();
+        let index = CardIndex::new(
+            names
+                .iter()
+                .map(String::as_str)
+                .chain(["Dark Magician"])
+                .map(|name| card(name, serde_json::json!({})))
+                .collect(),
+        );
+        let main: Vec<&str> = names.iter().flat_map(|name| [name.as_str(); 3]).collect();
+        let mut deck = deck(&main, &[], &[]);
+        let custom = |username: &str| DeckCard {
+            id: Some(7),
+            name: "Homebrew".to_string(),
+            username: Some(username.to_string()),
+        };
+        deck.side.push(custom("someone"));
+        let allowed = [AllowedCustom {
+            id: None,
+            name: Some("Homebrew".to_string()),
+            username: Some("someone".to_string()),
+        }];
+
+        let (valid, report) = validate_deck(&index, &allowed, &Banlist::new(), &deck);
+        assert_eq!(report, "This deck is valid.");
+        assert!(valid, "{report}");
+
+        deck.side.push(custom("someone else"));
+        deck.main[0].name = "Dark Magicain".to_string();
+        let (valid, report) = validate_deck(&index, &allowed, &Banlist::new(), &deck);
+        assert!(!valid);
+        assert!(report.contains("following 2 invalid cards"), "{report}");
+        assert!(
+            report.contains("**Dark Magicain** x 1 (did you mean **Dark Magician**?)"),
+            "{report}"
+        );
+        assert!(report.contains("*(custom by: someone else)*"), "{report}");
+        assert!(!report.contains("custom by: someone)"), "{report}");
+    }
+
+    #[test]
+    fn deck_reports_ping_no_one() {
+        let index = CardIndex::new(vec![card("Dark Magician", serde_json::json!({}))]);
+        let deck = deck(&["@everyone"], &[], &[]);
+        let (valid, report) = validate_deck(&index, &[], &Banlist::new(), &deck);
+        assert!(!valid);
+        assert!(report.contains("**@everyone** x 1"), "{report}");
+
+        let replies = deck_report(&report);
+        assert_eq!(replies.len(), 1);
+        assert!(replies[0].content.as_ref().unwrap().contains("@everyone"));
+        assert_eq!(
+            replies[0].allowed_mentions,
+            Some(CreateAllowedMentions::new())
+        );
+    }
+
+    #[test]
+    fn long_deck_reports_are_split_and_totalled_once_per_card() {
+        let index = CardIndex::new(vec![card("Dark Magician", serde_json::json!({}))]);
+        let main: Vec<String> = (1..=60)
+            .map(|i| format!("Some Exceptionally Long Homebrew Card Name {i}"))
+            .collect();
+        let main: Vec<&str> = main.iter().map(String::as_str).collect();
+        let mut deck = deck(&main, &[], &["Dark Magicain"]);
+        deck.main.extend(deck.side.clone());
+        deck.main.push(deck.side[0].clone());
+
+        let (_, report) = validate_deck(&index, &[], &Banlist::new(), &deck);
+        assert!(report.len() > MESSAGE_LIMIT, "{}", report.len());
+        let totals: Vec<_> = report
+            .lines()
+            .filter(|line| line.contains("(Main"))
+            .filter(|line| line.contains("Dark Magicain"))
+            .collect();
+        assert_eq!(totals, ["- **Dark Magicain** x 3 (Main 2, Side 1)"]);
+
+        let replies = deck_report(&report);
+        assert!(replies.len() > 1);
+        let pieces: Vec<_> = replies.iter().map(|r| r.content.clone().unwrap()).collect();
+        assert!(pieces.iter().all(|p| p.chars().count() <= MESSAGE_LIMIT));
+        assert_eq!(pieces.concat(), report);
+        assert!(replies.iter().all(|r| r.allowed_mentions.is_some()));
+    }
+
+    #[test]
+    fn verdicts_react_with_a_check_or_a_cross() {
+        assert_eq!(verdict_emoji(true), '✅');
+        assert_eq!(verdict_emoji(false), '❌');
+    }
+
+    #[test]
+    fn an_unrestricted_deck_has_no_banlist_violations() {
+        let banlist = Banlist::from([("Pot of Greed".to_string(), 0)]);
+        let deck = deck(&["Dark Magician"; 3], &[], &[]);
+        let violations = BanlistViolations::new(&banlist, &deck);
+        assert!(violations.forbidden.is_empty());
+        assert!(violations.over_limit.is_empty());
+    }
+
+    #[test]
+    fn stat_ranges_only_pass_unknown_stats_when_asked() {
+        let range = StatRange {
+            min: Some(1000),
+            max: Some(2000),
+        };
+        assert!(range.contains(Some(1500), false));
+        assert!(!range.contains(Some(2500), false));
+        assert!(!range.contains(Some(-1), false));
+        assert!(range.contains(Some(-1), true));
+        assert!(!range.contains(None, true));
+        assert!(StatRange::default().contains(Some(-1), false));
+        assert!(StatRange::default().contains(None, false));
+
+        let cards = CardIndex::new(vec![
+            card("Strong", serde_json::json!({"atk": 1800, "def": 1000})),
+            card("Weak", serde_json::json!({"atk": 500, "def": 1000})),
+            card("Unknown", serde_json::json!({"atk": -1, "def": 0})),
+            card("Spell", serde_json::json!({})),
+        ]);
+        let args = |unknown_stats| FilterArgs {
+            min_atk: Some(1500),
+            unknown_stats: Some(unknown_stats),
+            ..Default::default()
+        };
+        let skip = args(UnknownStats::Skip).filter().unwrap();
+        assert_eq!(passing(&cards, &skip), ["Strong"]);
+        let include = args(UnknownStats::Include).filter().unwrap();
+        assert_eq!(passing(&cards, &include), ["Strong", "Unknown"]);
+        let only = args(UnknownStats::Only).filter().unwrap();
+        assert_eq!(passing(&cards, &only), [] as [&str; 0]);
+
+        // `/query` ranges read the same way.
+        let query = query::parse("atk>=1500").unwrap().filter;
+        assert_eq!(passing(&cards, &query), ["Strong"]);
+        let query = query::parse("def<=0").unwrap().filter;
+        assert_eq!(passing(&cards, &query), ["Unknown"]);
+        let query = query::parse("atk<=0").unwrap().filter;
+        assert_eq!(passing(&cards, &query), [] as [&str; 0]);
+
+        // "?" can't be asked for as a number; `unknown_stats` is the way.
+        let error = |query| query::parse(query).unwrap_err().to_string();
+        assert_eq!(error("atk:?"), "`?` is not a number for `atk`");
+        assert_eq!(error("def>=1.5"), "`1.5` is not a number for `def`");
+        assert_eq!(
+            error("atk>99999999999999999999"),
+            "`99999999999999999999` is not a number for `atk`"
+        );
+    }
+
+    #[test]
+    fn negative_filters_keep_cards_without_the_property() {
+        let cards = CardIndex::new(vec![
+            card(
+                "Dark Magician",
+                serde_json::json!({"attribute": "DARK", "archetype": "Dark Magician", "race": "Spellcaster"}),
+            ),
+            card("Pot of Greed", serde_json::json!({"race": "Normal"})),
+            card(
+                "Jinzo",
+                serde_json::json!({"attribute": "DARK", "race": "Machine"}),
+            ),
+            card(
+                "Token",
+                serde_json::json!({"attribute": "", "race": "Machine"}),
+            ),
+        ]);
+        let no_archetype = CardFilter {
+            no_archetype: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            passing(&cards, &no_archetype),
+            ["Pot of Greed", "Jinzo", "Token"]
+        );
+        let no_attribute = CardFilter {
+            no_attribute: true,
+            ..Default::default()
+        };
+        assert_eq!(passing(&cards, &no_attribute), ["Pot of Greed", "Token"]);
+
+        // Alongside the positive filters, each still has to pass.
+        let filter = CardFilter {
+            attribute: Some(Attribute::Dark),
+            ..no_archetype.clone()
+        };
+        assert_eq!(passing(&cards, &filter), ["Jinzo"]);
+        let filter = CardFilter {
+            race: Some("machine".to_string()),
+            ..no_attribute
+        };
+        assert_eq!(passing(&cards, &filter), ["Token"]);
+        let filter = CardFilter {
+            attribute: Some(Attribute::Dark),
+            no_attribute: true,
+            ..Default::default()
+        };
+        assert_eq!(passing(&cards, &filter), [] as [&str; 0]);
+
+        let query = query::parse("archetype:none race:machine").unwrap().filter;
+        assert_eq!(passing(&cards, &query), ["Jinzo", "Token"]);
+    }
+
+    #[test]
+    fn unknown_stats_only_keeps_monsters_with_a_question_mark() {
+        let cards = CardIndex::new(vec![
+            card("Unknown ATK", serde_json::json!({"atk": -1, "def": 1000})),
+            card("Unknown DEF", serde_json::json!({"atk": 2000, "def": -1})),
+            card("Known", serde_json::json!({"atk": 0, "def": 0})),
+            card("Spell", serde_json::json!({})),
+        ]);
+        let only = FilterArgs {
+            unknown_stats: Some(UnknownStats::Only),
+            ..Default::default()
+        }
+        .filter()
+        .unwrap();
+        assert_eq!(passing(&cards, &only), ["Unknown ATK", "Unknown DEF"]);
+
+        // A range on the stat that is known still applies.
+        let filter = CardFilter {
+            atk: StatRange {
+                min: Some(1500),
+                max: None,
+            },
+            ..only.clone()
+        };
+        assert_eq!(passing(&cards, &filter), ["Unknown DEF"]);
+        let filter = CardFilter {
+            def: StatRange {
+                min: None,
+                max: Some(1000),
+            },
+            ..only
+        };
+        assert_eq!(passing(&cards, &filter), ["Unknown ATK"]);
+    }
+
+    #[test]
+    fn combined_filters_must_all_pass() {
+        let cards = CardIndex::new(vec![
+            card(
+                "Dark Rank 4",
+                serde_json::json!({"frameType": "xyz", "attribute": "DARK", "level": 4}),
+            ),
+            card(
+                "Light Rank 4",
+                serde_json::json!({"frameType": "xyz", "attribute": "LIGHT", "level": 4}),
+            ),
+            card(
+                "Dark Rank 8",
+                serde_json::json!({"frameType": "xyz", "attribute": "DARK", "level": 8}),
+            ),
+            card(
+                "Dark Level 4",
+                serde_json::json!({"frameType": "effect", "attribute": "DARK", "level": 4}),
+            ),
+            card(
+                "Dark Link",
+                serde_json::json!({"frameType": "link", "attribute": "DARK", "linkval": 2}),
+            ),
+            card(
+                "No Attribute",
+                serde_json::json!({"frameType": "xyz", "level": 4}),
+            ),
+        ]);
+        let filter = CardFilter {
+            frame_type: Some(FrameType::Xyz),
+            attribute: Some(Attribute::Dark),
+            levels: vec![4],
+            ..Default::default()
+        };
+        assert_eq!(passing(&cards, &filter), ["Dark Rank 4"]);
+
+        // Cards without the level or attribute are turned away, not let
+        // through.
+        let filter = CardFilter {
+            attribute: Some(Attribute::Dark),
+            max_level: Some(8),
+            ..Default::default()
+        };
+        assert_eq!(
+            passing(&cards, &filter),
+            ["Dark Rank 4", "Dark Rank 8", "Dark Level 4"]
+        );
+        let filter = CardFilter {
+            frame_type: Some(FrameType::Xyz),
+            levels: vec![4],
+            ..Default::default()
+        };
+        assert_eq!(
+            passing(&cards, &filter),
+            ["Dark Rank 4", "Light Rank 4", "No Attribute"]
+        );
+        let filter = CardFilter {
+            frame_type: Some(FrameType::Link),
+            attribute: Some(Attribute::Dark),
+            linkval: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(passing(&cards, &filter), ["Dark Link"]);
+    }
+
+    #[test]
+    fn picks_random_cards_from_the_matches() {
+        use rand::SeedableRng as _;
+
+        let cards = CardIndex::new(vec![
+            card("Dark Magician", serde_json::json!({"frameType": "normal"})),
+            card("Pot of Greed", serde_json::json!({"frameType": "spell"})),
+            card("Raigeki", serde_json::json!({"frameType": "spell"})),
+            card("Mirror Force", serde_json::json!({"frameType": "trap"})),
+            card("Monster Reborn", serde_json::json!({"frameType": "spell"})),
+        ]);
+        let spells = CardFilter {
+            frame_type: Some(FrameType::Spell),
+            ..Default::default()
+        };
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let card = pick_random(cards.iter(), &spells, &mut rng).unwrap();
+            assert_eq!(card.frame_type, "spell", "{}", card.name);
+        }
+
+        // The same seed picks the same cards.
+        let sequence = |seed| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            (0..10)
+                .map(|_| {
+                    pick_random(cards.iter(), &spells, &mut rng)
+                        .unwrap()
+                        .name
+                        .clone()
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sequence(42), sequence(42));
+
+        let links = CardFilter {
+            frame_type: Some(FrameType::Link),
+            ..Default::default()
+        };
+        assert!(pick_random(cards.iter(), &links, &mut rng).is_none());
+    }
+
+    #[test]
+    fn random_picks_cover_the_matches_evenly() {
+        use rand::SeedableRng as _;
+
+        let cards = CardIndex::new(
+            ["Pot of Greed", "Raigeki", "Monster Reborn"]
+                .into_iter()
+                .map(|name| card(name, serde_json::json!({"frameType": "spell"})))
+                .collect(),
+        );
+        let mut rng = rand::rngs::StdRng::seed_from_u64(7);
+        let mut picks: BTreeMap<&str, usize> = BTreeMap::new();
+        for _ in 0..3000 {
+            let card = pick_random(cards.iter(), &CardFilter::default(), &mut rng).unwrap();
+            *picks.entry(card.name.as_str()).or_default() += 1;
+        }
+        assert_eq!(picks.len(), 3);
+        // Each comes up about a thousand times.
+        for (name, count) in picks {
+            assert!((850..=1150).contains(&count), "{name}: {count}");
+        }
+    }
+
+    #[test]
+    fn ranks_exact_then_word_then_prefix_matches() {
+        let cards = CardIndex::new(
+            [
+                "Apprentice Magicians",
+                "Magicians Rod",
+                "Skilled Dark Magician",
+                "Dark Magician",
+                "Magician",
+                "Toon Dark Magician",
+            ]
+            .into_iter()
+            .map(|name| card(name, serde_json::json!({})))
+            .collect(),
+        );
+        // The excluded term keeps the query from naming "Magician" exactly,
+        // which would list it alone.
+        assert_eq!(
+            searched(
+                &cards,
+                "magician * -toon",
+                &CardFilter::default(),
+                SortOrder::Relevance
+            ),
+            [
+                "Magician",
+                "Dark Magician",
+                "Skilled Dark Magician",
+                "Magicians Rod",
+                "Apprentice Magicians",
+            ]
+        );
+        assert_eq!(
+            searched(
+                &cards,
+                "magician * -toon",
+                &CardFilter::default(),
+                SortOrder::Name
+            ),
+            [
+                "Apprentice Magicians",
+                "Dark Magician",
+                "Magician",
+                "Magicians Rod",
+                "Skilled Dark Magician",
+            ]
+        );
+    }
+
+    #[test]
+    fn breaks_relevance_ties_by_length_then_name() {
+        let cards = CardIndex::new(
+            [
+                "Fake Magician",
+                "Black Magician",
+                "Dark Magician",
+                "Magician Girl",
+                "Magician Of Dark",
+            ]
+            .into_iter()
+            .map(|name| card(name, serde_json::json!({})))
+            .collect(),
+        );
+        assert_eq!(
+            searched(
+                &cards,
+                "magician",
+                &CardFilter::default(),
+                SortOrder::Relevance
+            ),
+            [
+                "Dark Magician",
+                "Fake Magician",
+                "Magician Girl",
+                "Black Magician",
+                "Magician Of Dark",
+            ]
+        );
+    }
+
+    #[test]
+    fn an_exact_name_beats_longer_names_containing_it() {
+        let cards = CardIndex::new(vec![
+            card("Dark Magician Girl", serde_json::json!({})),
+            card("Dark Magician", serde_json::json!({})),
+            card("Toon Dark Magician", serde_json::json!({"aliases": ["DM"]})),
+            card(
+                "Dark Magician of Chaos",
+                serde_json::json!({"aliases": ["DMOC"]}),
+            ),
+        ]);
+        let any = CardFilter::default();
+        for sort in [SortOrder::Relevance, SortOrder::Name] {
+            assert_eq!(
+                searched(&cards, "dark magician", &any, sort),
+                ["Dark Magician"]
+            );
+        }
+        assert_eq!(
+            searched(&cards, "DARK-MAGICIAN ", &any, SortOrder::Relevance),
+            ["Dark Magician"]
+        );
+        assert_eq!(
+            searched(&cards, "dmoc", &any, SortOrder::Relevance),
+            ["Dark Magician of Chaos"]
+        );
+        // Without an exact name the listing stays.
+        assert_eq!(
+            searched(&cards, "dark magician g", &any, SortOrder::Relevance),
+            ["Dark Magician Girl"]
+        );
+        assert_eq!(
+            searched(&cards, "magician", &any, SortOrder::Relevance).len(),
+            4
+        );
+
+        // With `exact_name` set, nothing but an exact name is listed.
+        let exact = CardFilter {
+            exact_name: true,
+            ..Default::default()
+        };
+        assert!(searched(&cards, "magician", &exact, SortOrder::Relevance).is_empty());
+    }
+
+    #[test]
+    fn permalinks_skip_unset_params_and_escape_the_rest() {
+        assert_eq!(
+            search_permalink(&[("name", None), ("effect", Some("  "))]),
+            "https://theplunderpirates.cc/"
+        );
+        assert_eq!(
+            search_permalink(&[
+                ("name", Some(" Dark Magician ")),
+                ("effect", None),
+                ("level", Some("4,7")),
+                ("race", Some("Beast & Co?")),
+            ]),
+            "https://theplunderpirates.cc/?name=Dark+Magician&level=4%2C7&race=Beast+%26+Co%3F"
+        );
+    }
+
+    #[test]
+    fn warns_about_each_unknown_key_once() {
+        let cards = [
+            card("Dark Magician", serde_json::json!({"foo": 1})),
+            card(
+                "Dark Magician Girl",
+                serde_json::json!({"foo": 2, "bar": null}),
+            ),
+        ];
+        assert_eq!(cards[0].extra["foo"], 1);
+        assert!(cards[1].extra.contains_key("bar"));
+
+        let mut reported = BTreeSet::new();
+        assert_eq!(warn_unknown_keys(&cards, &mut reported), ["bar", "foo"]);
+        assert!(warn_unknown_keys(&cards, &mut reported).is_empty());
+
+        let mut more = cards.to_vec();
+        more.push(card(
+            "Blue-Eyes White Dragon",
+            serde_json::json!({"baz": true}),
+        ));
+        assert_eq!(warn_unknown_keys(&more, &mut reported), ["baz"]);
+        assert_eq!(reported.len(), 3);
+    }
+
+    #[test]
+    fn parses_level_lists() {
+        assert_eq!(parse_levels(None).unwrap(), Vec::<u32>::new());
+        assert_eq!(parse_levels(Some(" ")).unwrap(), Vec::<u32>::new());
+        assert_eq!(parse_levels(Some("4, 6,8")).unwrap(), [4, 6, 8]);
+        assert_eq!(parse_levels(Some("4,,8,")).unwrap(), [4, 8]);
+        assert_eq!(
+            parse_levels(Some("4, four")).unwrap_err().to_string(),
+            "`four` is not a level"
+        );
+        assert!(parse_levels(Some("-1")).is_err());
+    }
+
+    #[test]
+    fn parses_passcode_ranges() {
+        assert_eq!(parse_passcode_range(None).unwrap(), (None, None));
+        assert_eq!(parse_passcode_range(Some(" ")).unwrap(), (None, None));
+        assert_eq!(
+            parse_passcode_range(Some("100-200")).unwrap(),
+            (Some(100), Some(200))
+        );
+        assert_eq!(
+            parse_passcode_range(Some("100 - ")).unwrap(),
+            (Some(100), None)
+        );
+        assert_eq!(
+            parse_passcode_range(Some("-200")).unwrap(),
+            (None, Some(200))
+        );
+        assert_eq!(
+            parse_passcode_range(Some("46986414")).unwrap(),
+            (Some(46986414), Some(46986414))
+        );
+        assert!(parse_passcode_range(Some("abc")).is_err());
+        assert!(parse_passcode_range(Some("1-2-3")).is_err());
+    }
+
+    #[test]
+    fn filters_and_sorts_by_passcode() {
+        let cards = [
+            card("A", serde_json::json!({"number_value": 300})),
+            card("B", serde_json::json!({})),
+            card("C", serde_json::json!({"number_value": 100})),
+            card("D", serde_json::json!({"number_value": 200})),
+        ];
+        let filter = CardFilter {
+            min_passcode: Some(150),
+            ..Default::default()
+        };
+        let matching: Vec<&CardDatum> = cards.iter().filter(|c| filter.apply(c)).collect();
+        assert_eq!(names(&matching), ["A", "D"]);
+
+        let mut sorted: Vec<&CardDatum> = cards.iter().collect();
+        sort_missing_last(&mut sorted, |c| c.number_value, false);
+        assert_eq!(names(&sorted), ["C", "D", "A", "B"]);
+        sort_missing_last(&mut sorted, |c| c.number_value, true);
+        assert_eq!(names(&sorted), ["A", "D", "C", "B"]);
+    }
+
+    fn message(content: &str) -> serenity::Message {
+        let mut message = serenity::Message::default();
+        message.kind = serenity::MessageType::Regular;
+        message.content = content.to_string();
+        message
+    }
+
+    #[test]
+    fn warns_about_missing_content_once() {
+        let warned = AtomicBool::new(false);
+        assert!(!warn_if_content_missing(
+            &message("<Dark Magician>"),
+            &warned
+        ));
+        assert!(warn_if_content_missing(&message(""), &warned));
+        assert!(!warn_if_content_missing(&message(""), &warned));
+    }
+
+    #[test]
+    fn only_blank_messages_from_people_lack_content() {
+        assert!(lacks_content(&message("")));
+
+        let mut reply = message("");
+        reply.kind = serenity::MessageType::InlineReply;
+        assert!(lacks_content(&reply));
+
+        let mut pin = message("");
+        pin.kind = serenity::MessageType::PinsAdd;
+        assert!(!lacks_content(&pin));
+
+        let mut from_bot = message("");
+        from_bot.author.bot = true;
+        assert!(!lacks_content(&from_bot));
+
+        let mut with_embed = message("");
+        with_embed.embeds.push(serenity::Embed::default());
+        assert!(!lacks_content(&with_embed));
+    }
+
+    #[test]
+    fn only_failed_searches_are_ephemeral() {
+        let mut results = SearchResults::default();
+        assert!(results.is_ephemeral());
+
+        results.suggestions = vec![card("Dark Magician", serde_json::json!({}))];
+        assert!(results.is_ephemeral());
+        let embed = serde_json::to_value(reply_embed(&results, 0)).unwrap();
+        assert_eq!(embed["title"], "No cards found");
+
+        results.cards = results.suggestions.clone();
+        results.total = 1;
+        assert!(!results.is_ephemeral());
+        let embed = serde_json::to_value(reply_embed(&results, 0)).unwrap();
+        assert_eq!(embed["title"], "Dark Magician");
+    }
+
+    #[test]
+    fn image_filenames_match_the_tpp_site() {
+        assert_eq!(
+            image_filename("Ash-Blossom & Joyous Spring"),
+            "Ash-Blossom%20_%20Joyous%20Spring.jpg"
+        );
+        assert_eq!(
+            image_filename("Magician's  Circle"),
+            "Magician_s%20Circle.jpg"
+        );
+        assert_eq!(image_filename("Dark~Magician_2"), "Dark~Magician_2.jpg");
+        assert_eq!(image_filename("#1 Doré"), "%231%20Dor%C3%A9.jpg");
+    }
+
+    #[test]
+    fn bench_report_averages_per_run() {
+        let report = bench_report(
+            4,
+            &[
+                ("short name", Duration::from_millis(8), 12),
+                ("broad effect", Duration::from_millis(16), 300),
+            ],
+        );
+        assert_eq!(
+            report,
+            "Timings over 4 runs each:\n\
+             - short name: 2.00ms (12 matches)\n\
+             - broad effect: 4.00ms (300 matches)\n\
+             **Average over all searches:** 3.00ms"
+        );
+    }
+
+    #[test]
+    fn attaches_raw_decks_pretty_printed() {
+        let reply = deck_raw_reply(r#"{"action":"load deck","main":[1]}"#.to_string()).unwrap();
+        let attachment = &reply.attachments[0];
+        assert_eq!(attachment.filename, "deck.json");
+        let body = String::from_utf8(attachment.data.clone()).unwrap();
+        assert_eq!(
+            body,
+            "{\n  \"action\": \"load deck\",\n  \"main\": [\n    1\n  ]\n}"
+        );
+        assert_eq!(
+            reply.content.as_deref(),
+            Some(format!("DuelingBook returned {} bytes.", body.len()).as_str())
+        );
+
+        let reply = deck_raw_reply("<html>Not found</html>".to_string()).unwrap();
+        assert_eq!(reply.attachments[0].data, b"<html>Not found</html>");
+    }
+
+    #[test]
+    fn renders_a_card_as_plain_text() {
+        let card = card(
+            "Dark Magician",
+            serde_json::json!({
+                "full_type": "Normal Monster",
+                "desc": "The *ultimate* wizard.\n- in terms of attack",
+                "attribute": "DARK",
+                "level": 7,
+                "atk": 2500,
+                "def": 2100,
+                "aliases": ["Dark_Magician"],
+            }),
+        );
+        assert_eq!(
+            card.plain_text(Some("Ruling > errata")),
+            "**Dark Magician**\n\
+             Normal Monster\n\
+             DARK, Level 7, ATK 2500 / DEF 2100\n\
+             \n\
+             The \\*ultimate\\* wizard.\n\\- in terms of attack\n\
+             \n\
+             Also treated as: Dark\\_Magician\n\
+             \n\
+             Note: Ruling > errata"
+        );
+        assert!(!card.plain_text(None).contains("Note:"));
+
+        let embed =
+            serde_json::to_value(card.make_embed(Utc::now(), Some("Ruling > errata"))).unwrap();
+        let fields = embed["fields"].as_array().unwrap();
+        let note = fields.last().unwrap();
+        assert_eq!(note["name"], "Note");
+        assert_eq!(note["value"], "Ruling > errata");
+        assert_eq!(note["inline"], false);
+        let embed = serde_json::to_value(card.make_embed(Utc::now(), None)).unwrap();
+        assert!(embed["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|field| field["name"] != "Note"));
+    }
+
+    #[test]
+    fn spoiler_embeds_hide_the_text_and_link_the_art() {
+        let card = card(
+            "Dark Magician",
+            serde_json::json!({"desc": "The ultimate wizard.", "image_url": "https://example.com/dm.jpg"}),
+        );
+        let field = |embed: &serde_json::Value, name: &str| {
+            embed["fields"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|field| field["name"] == name)
+                .map(|field| field["value"].clone())
+        };
+
+        let embed = serde_json::to_value(card.make_spoiler_embed(Utc::now(), None)).unwrap();
+        assert_eq!(embed["description"], "||The ultimate wizard.||");
+        assert_eq!(
+            field(&embed, "Art").unwrap(),
+            "||[View art](https://example.com/dm.jpg)||"
+        );
+        assert!(embed.get("image").is_none(), "{embed}");
+
+        let embed = serde_json::to_value(card.make_embed(Utc::now(), None)).unwrap();
+        assert_eq!(embed["description"], "The ultimate wizard.");
+        assert_eq!(embed["image"]["url"], "https://example.com/dm.jpg");
+        assert_eq!(field(&embed, "Art"), None);
+    }
+
+    #[test]
+    fn plain_text_shows_ranks_links_and_unknown_stats() {
+        let xyz = card(
+            "Utopia",
+            serde_json::json!({"frameType": "xyz", "level": 4, "atk": -1, "def": 2000}),
+        );
+        assert!(xyz
+            .plain_text(None)
+            .contains("\nRank 4, ATK ? / DEF 2000\n"));
+        let link = card(
+            "Decode Talker",
+            serde_json::json!({"frameType": "link", "linkval": 3, "atk": 2300}),
+        );
+        assert!(link.plain_text(None).contains("\nLink-3, ATK 2300\n"));
+    }
+
+    #[test]
+    fn image_check_uses_the_art_the_embed_would() {
+        let index = CardIndex::new(vec![
+            card(
+                "Dark Magician",
+                serde_json::json!({"image_url": "https://example.com/dm.png"}),
+            ),
+            card("Blue-Eyes White Dragon", serde_json::json!({})),
+        ]);
+        assert_eq!(
+            image_check_candidates(&index, "dark magician"),
+            [
+                "https://example.com/dm.png".to_string(),
+                format!("{IMG_BASE}Dark%20Magician.jpg"),
+            ]
+        );
+        assert_eq!(
+            image_check_candidates(&index, "Blue-Eyes White Dragon"),
+            [format!("{IMG_BASE}Blue-Eyes%20White%20Dragon.jpg")]
+        );
+        assert_eq!(
+            image_check_candidates(&index, "Not A Card"),
+            [format!("{IMG_BASE}Not%20A%20Card.jpg")]
+        );
+    }
+
+    #[test]
+    fn audits_cards_missing_display_data() {
+        let cards = [
+            card(
+                "Complete",
+                serde_json::json!({
+                    "desc": "Text.", "full_type": "Normal Monster", "frameType": "normal",
+                    "image_url": "https://example.com/a.jpg", "atk": 0, "def": 0,
+                }),
+            ),
+            card(
+                "Blank",
+                serde_json::json!({"desc": " ", "frameType": "effect", "image_url": ""}),
+            ),
+            card(
+                "Textless Spell",
+                serde_json::json!({"full_type": "Spell", "frameType": "spell", "image_url": "https://example.com/b.jpg"}),
+            ),
+        ];
+        let report = AuditReport::new(&cards);
+        assert_eq!(report.empty_desc, ["Blank", "Textless Spell"]);
+        assert_eq!(report.empty_full_type, ["Blank"]);
+        assert_eq!(report.missing_art, ["Blank"]);
+        assert_eq!(report.missing_stats, ["Blank"]);
+        assert_eq!(
+            report.render(),
+            "## Empty description (2)\n- Blank\n- Textless Spell\n\
+             ## Empty type line (1)\n- Blank\n\
+             ## Missing art (1)\n- Blank\n\
+             ## Monsters missing ATK and DEF (1)\n- Blank"
+        );
+        let reply = report.reply();
+        assert_eq!(reply.content, Some(report.render()));
+        assert!(reply.attachments.is_empty());
+
+        let reply = AuditReport::new(&cards[..1]).reply();
+        assert_eq!(reply.content.as_deref(), Some("No problems found."));
+
+        let many: Vec<_> = (0..200)
+            .map(|i| card(&format!("Unfinished Card {i}"), serde_json::json!({})))
+            .collect();
+        let report = AuditReport::new(&many);
+        let reply = report.reply();
+        assert_eq!(
+            reply.content.as_deref(),
+            Some("The audit report is attached.")
+        );
+        assert_eq!(reply.attachments[0].filename, "audit.md");
+        assert_eq!(reply.attachments[0].data, report.render().into_bytes());
+    }
+
+    #[test]
+    fn level_curves_count_and_chart_the_levels() {
+        let monster = |level: u32| {
+            card(
+                "Monster",
+                serde_json::json!({"frameType": "effect", "level": level}),
+            )
+        };
+        let mut cards: Vec<_> = [4, 8, 4, 4, 8, 4].into_iter().map(monster).collect();
+        cards.push(card(
+            "Link",
+            serde_json::json!({"frameType": "link", "linkval": 2}),
+        ));
+        cards.push(card("Spell", serde_json::json!({"frameType": "spell"})));
+        cards.push(card("Trap", serde_json::json!({"frameType": "trap"})));
+        cards.push(card("Trap", serde_json::json!({"frameType": "trap"})));
+
+        let curve = LevelCurve::new(&cards);
+        assert_eq!(curve.levels, BTreeMap::from([(4, 4), (8, 2)]));
+        assert_eq!((curve.unleveled, curve.spells, curve.traps), (1, 1, 2));
+        assert_eq!(
+            curve.render(),
+            format!(
+                "```\nLv  4 {} 4\nLv  8 {} 2\nNo Lv {} 1\n```\nSpells: 1 | Traps: 2",
+                "█".repeat(20),
+                "█".repeat(10),
+                "█".repeat(5)
+            )
+        );
+
+        assert_eq!(
+            LevelCurve::new(&cards[7..]).render(),
+            "This archetype has no monsters.\nSpells: 1 | Traps: 2"
+        );
+    }
+
+    #[test]
+    fn shared_support_needs_a_tie_to_each_archetype() {
+        let cards = CardIndex::new(vec![
+            card(
+                "Both In Text",
+                serde_json::json!({"desc": "Add 1 \"Blue-Eyes\" and 1 \"Dark Magician\" card."}),
+            ),
+            card(
+                "Blue-Eyes Member",
+                serde_json::json!({"archetype": "Blue-Eyes", "desc": "Summon a DARK MAGICIAN."}),
+            ),
+            card(
+                "Magician Member",
+                serde_json::json!({"archetype": "Dark Magician", "desc": "Add a Blue-Eyes monster."}),
+            ),
+            card(
+                "Only Blue-Eyes",
+                serde_json::json!({"archetype": "Blue-Eyes", "desc": "Draw 1."}),
+            ),
+            card(
+                "Only In Text",
+                serde_json::json!({"desc": "Banish a Blue-Eyes monster."}),
+            ),
+        ]);
+        assert_eq!(
+            names(&shared_support_cards(&cards, "Blue-Eyes", "dark magician")),
+            ["Blue-Eyes Member", "Both In Text", "Magician Member"]
+        );
+        assert!(shared_support_cards(&cards, "Blue-Eyes", "Red-Eyes").is_empty());
+    }
+
+    #[test]
+    fn finds_inline_lookups_up_to_the_limit() {
+        assert_eq!(
+            inline_lookups("<@123> try <Dark Magician> with < raigeki > <:emoji:1> <t:99:R>"),
+            ["Dark Magician", "raigeki"]
+        );
+        assert_eq!(inline_lookups("<a> <b> <a> <c> <d> <e>"), ["a", "b", "c"]);
+        assert_eq!(
+            inline_lookups("<https://example.com> </search:1>"),
+            [] as [&str; 0]
+        );
+        // Markup counts towards the spans searched, but not the lookups.
+        let content = format!("{} <Raigeki>", "<@1> ".repeat(INLINE_SPAN_LIMIT));
+        assert_eq!(inline_lookups(&content), [] as [&str; 0]);
+        let content = format!("{} <Raigeki>", "<@1> ".repeat(INLINE_SPAN_LIMIT - 1));
+        assert_eq!(inline_lookups(&content), ["Raigeki"]);
+    }
+
+    #[test]
+    fn long_type_lines_get_a_field_of_their_own() {
+        let inline = |full_type: &str| {
+            let card = card("Monster", serde_json::json!({"full_type": full_type}));
+            let embed = serde_json::to_value(card.make_embed(Utc::now(), None)).unwrap();
+            let field = embed["fields"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .find(|field| field["name"] == "Type")
+                .unwrap()
+                .clone();
+            assert_eq!(field["value"], full_type);
+            field["inline"].as_bool().unwrap()
+        };
+        assert!(inline("Effect Monster"));
+        assert!(inline(&"x".repeat(INLINE_TYPE_LIMIT)));
+        assert!(!inline(&"x".repeat(INLINE_TYPE_LIMIT + 1)));
+        assert!(!inline("Pendulum Tuner Effect Synchro Monster"));
+    }
+
+    #[test]
+    fn environment_flags_turn_commands_off() {
+        let vars = [
+            ("ENABLE_CHECK_DECK", "false"),
+            ("ENABLE_SEARCH", "true"),
+            ("ENABLE_COUNT", "nope"),
+            ("ENABLE_SERACH", "false"),
+            ("ENABLE_RANDOM", "false"),
+            ("DISABLE_STATS", "true"),
+        ]
+        .map(|(key, value)| (key.to_string(), value.to_string()));
+        let names = ["search", "count", "check_deck", "random", "stats"];
+        let (disabled, unknown) = command_flags(&names, vars);
+        assert_eq!(disabled, ["check_deck", "random"]);
+        assert_eq!(unknown, ["ENABLE_SERACH"]);
+
+        assert_eq!(command_flags(&names, []), (vec![], vec![]));
+    }
+
+    #[test]
+    fn autocomplete_offers_at_most_a_page_of_names() {
+        let mut cards: Vec<_> = (0..60)
+            .map(|i| card(&format!("Magician {i:02}"), serde_json::json!({})))
+            .collect();
+        cards.push(card("Dark Magician", serde_json::json!({})));
+        cards.push(card("Toon Magicians", serde_json::json!({})));
+        cards.push(card(
+            "Aliased",
+            serde_json::json!({"aliases": ["Magician Alias"]}),
+        ));
+        cards.push(card(&"Magician ".repeat(20), serde_json::json!({})));
+        let cards = CardIndex::new(cards);
+        let any = SearchTerms::parse("");
+
+        let names = autocomplete_names(&cards, "magician", &any);
+        assert_eq!(names.len(), AUTOCOMPLETE_LIMIT);
+        assert_eq!(names[0], "Magician 00");
+        assert!(
+            names.iter().all(|name| name.starts_with("Magician")),
+            "{names:?}"
+        );
+        // Discord rejects choices over 100 characters.
+        let names = autocomplete_names(&cards, "magician magician", &any);
+        assert_eq!(
+            names,
+            ["Magician ".repeat(20).chars().take(100).collect::<String>()]
+        );
+
+        let names = autocomplete_names(&cards, "dark mag", &any);
+        assert_eq!(names, ["Dark Magician"]);
+        let names = autocomplete_names(&cards, "magicians", &any);
+        assert_eq!(names, ["Toon Magicians"]);
+        let names = autocomplete_names(&cards, "magician alias", &any);
+        assert_eq!(names, ["Aliased"]);
+        // Typos fall back to trigram suggestions, still within the limit.
+        let names = autocomplete_names(&cards, "magican", &any);
+        assert_eq!(names.len(), AUTOCOMPLETE_LIMIT);
+    }
+
+    #[test]
+    fn lists_the_longest_texts_first_with_ties_by_name() {
+        let cards = [
+            card("Short", serde_json::json!({"desc": "Draw 1 card."})),
+            card(
+                "Zeta",
+                serde_json::json!({"desc": "One two three four five."}),
+            ),
+            card(
+                "Alpha",
+                serde_json::json!({"desc": "Five four three two one."}),
+            ),
+            card("Wordy", serde_json::json!({"desc": "a b c d e f g h"})),
+        ];
+        let listed = |by, count| {
+            longest_texts(cards.iter(), by, count)
+                .into_iter()
+                .map(|(length, card)| (length, card.name.as_str()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            listed(TextLength::Characters, 4),
+            [(24, "Alpha"), (24, "Zeta"), (15, "Wordy"), (12, "Short")]
+        );
+        assert_eq!(
+            listed(TextLength::Words, 3),
+            [(8, "Wordy"), (5, "Alpha"), (5, "Zeta")]
+        );
+        assert_eq!(listed(TextLength::Words, 10).len(), 4);
+    }
+
+    #[test]
+    fn summarizes_commands_within_discord_limits() {
+        let summary = command_summary(&[count(), status()]).unwrap();
+        assert!(summary.starts_with("count: {\n"), "{summary}");
+        assert!(summary.contains("\nstatus: {\n"), "{summary}");
+        assert!(summary.contains("\"name\": \"count\""), "{summary}");
+
+        let mut prefix_only = count();
+        prefix_only.slash_action = None;
+        prefix_only.name = "legacy".to_string();
+        assert_eq!(
+            command_summary(&[prefix_only]).unwrap(),
+            "legacy (not a slash command)"
+        );
+
+        let mut wordy = status();
+        wordy.description = Some("x".repeat(101));
+        let error = command_summary(&[wordy]).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "The description of `status` in /status is over 100 characters"
+        );
+
+        let mut crowded = search();
+        while crowded.parameters.len() <= 25 {
+            crowded.parameters.extend(count().parameters);
+        }
+        let options = crowded.parameters.len();
+        let error = command_summary(&[crowded]).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!("/search has {options} options, but Discord allows 25")
+        );
+    }
+
+    #[test]
+    fn the_card_of_the_day_is_stable_within_a_day() {
+        let cards: Vec<_> = (0..50)
+            .map(|i| card(&format!("Card {i:02}"), serde_json::json!({})))
+            .collect();
+        let day = |d| chrono::NaiveDate::from_ymd_opt(2026, 10, d).unwrap();
+        let today = card_of_the_day(cards.iter(), day(14)).unwrap();
+        assert_eq!(
+            card_of_the_day(cards.iter(), day(14)).unwrap().name,
+            today.name
+        );
+        // Reordered card data picks the same card.
+        assert_eq!(
+            card_of_the_day(cards.iter().rev(), day(14)).unwrap().name,
+            today.name
+        );
+        // The index depends on nothing but the date, so it can't change with
+        // a restart or a new Rust release.
+        assert_eq!(
+            card_of_the_day_index(day(14), 50),
+            card_of_the_day_index(day(14), 50)
+        );
+
+        let picks: HashSet<_> = (1..=31)
+            .map(|d| card_of_the_day(cards.iter(), day(d)).unwrap().name.clone())
+            .collect();
+        assert!(picks.len() > 10, "{picks:?}");
+        assert!(card_of_the_day([].into_iter(), day(14)).is_none());
+    }
+
+    #[test]
+    fn image_check_table_has_a_row_per_name() {
+        let rows = [
+            ("A", "https://example.com/a.jpg".to_string(), Ok(true)),
+            ("B", "https://example.com/b.jpg".to_string(), Ok(false)),
+        ];
+        assert_eq!(
+            image_check_table(&rows),
+            "| Name | Image URL | Reachable |\n\
+             | --- | --- | --- |\n\
+             | A | https://example.com/a.jpg | yes |\n\
+             | B | https://example.com/b.jpg | no |\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn bounds_how_many_tasks_run_at_once() {
+        let permits = tokio::sync::Semaphore::new(3);
+        let running = std::sync::atomic::AtomicUsize::new(0);
+        let most = std::sync::atomic::AtomicUsize::new(0);
+        let tasks = (0..20).map(|i| {
+            let (running, most) = (&running, &most);
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                i
+            }
+        });
+        let outputs = join_bounded(&permits, tasks).await;
+        assert_eq!(outputs, (0..20).collect::<Vec<_>>());
+        assert_eq!(most.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::disallowed_characters;
+
+/// Reduce text to what can be typed on a plain keyboard: compatibility
+/// decomposition with the combining marks dropped (so "é" becomes "e"), and
+/// typographic quotes and dashes replaced by their ASCII forms.
+fn fold_unicode(text: &str) -> String {
+    const NFKD: icu_normalizer::DecomposingNormalizer =
+        icu_normalizer::DecomposingNormalizer::new_nfkd();
+    NFKD.normalize(text)
+        .chars()
+        .filter(|c| !is_combining_mark(*c))
+        .map(|c| match c {
+            '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => '\'',
+            '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => '"',
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+            c => c,
+        })
+        .collect()
+}
+
+fn is_combining_mark(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}')
+}
+
+/// The form of a query or card text used for matching: lowercase, with the
+/// disallowed punctuation removed and hyphens and runs of whitespace reduced to
+/// single spaces, so "ash blossom" finds "Ash-Blossom & Joyous Spring".
+pub fn normalize_search_term(term: &str) -> String {
+    disallowed_characters()
+        .replace_all(&fold_unicode(term), "")
+        .split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_punctuation_and_spacing() {
+        assert_eq!(
+            normalize_search_term("Ash-Blossom & Joyous Spring"),
+            "ash blossom joyous spring"
+        );
+        assert_eq!(
+            normalize_search_term("Magician's Circle"),
+            "magicians circle"
+        );
+        assert_eq!(normalize_search_term("  Dark   Magician "), "dark magician");
+        assert_eq!(normalize_search_term("Pot of Greed?!"), "pot of greed");
+        assert_eq!(normalize_search_term("Raigeki -- Break"), "raigeki break");
+    }
+
+    #[test]
+    fn folds_accents_and_typographic_quotes() {
+        assert_eq!(normalize_search_term("Doré"), normalize_search_term("Dore"));
+        assert_eq!(
+            normalize_search_term("D\u{2019}Artagnan"),
+            normalize_search_term("D'Artagnan")
+        );
+        assert_eq!(normalize_search_term("D\u{2019}Artagnan"), "dartagnan");
+        assert_eq!(
+            fold_unicode("\u{201C}Doré\u{201D} \u{2013} x"),
+            "\"Dore\" - x"
+        );
+    }
+}
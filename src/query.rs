@@ -0,0 +1,399 @@
+use anyhow::anyhow;
+
+use crate::{parse_levels, parse_passcode_range, Attribute, CardFilter, CardType, FrameType};
+
+/// The keys `parse` understands, listed when it meets one it doesn't.
+const SUPPORTED_KEYS: &str =
+    "name, effect, type, frame, attr, race, archetype, category, full_type, level, link, passcode, \
+     atk, def";
+
+/// A whole search written as one string, such as
+/// `atk>=2000 level<=4 attr:dark race:warrior "special summon"`.
+///
+/// Words separated by spaces are read one at a time:
+///
+/// - `key:value` (or `key=value`) sets a filter, and the numeric keys
+///   `level`, `passcode`, `atk` and `def` also take `<`, `<=`, `>` and `>=`
+/// - a value can be double quoted to include spaces, `race:"sea serpent"`
+/// - anything else is part of the name, with a double quoted phrase kept
+///   together, and the name parts have to appear in order
+/// - `name:` and `effect:` add to the name and effect queries, which use
+///   the [`SearchTerms`](crate::terms::SearchTerms) syntax
+#[derive(Debug, Default)]
+pub struct Query {
+    pub name: String,
+    pub effect: String,
+    pub filter: CardFilter,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn as_str(self) -> &'static str {
+        match self {
+            Op::Eq => ":",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+/// Split on whitespace outside double quotes, keeping the quotes. Unbalanced
+/// quotes are left as they are, like `SearchTerms` does.
+fn tokenize(input: &str) -> Vec<String> {
+    let quotes_balanced = input.matches('"').count().is_multiple_of(2);
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_quotes = false;
+    for c in input.chars() {
+        match c {
+            '"' if quotes_balanced => {
+                in_quotes = !in_quotes;
+                token.push(c);
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+/// Split `key<op>value` apart, or `None` for a bare word. Keys are ASCII
+/// letters and underscores, so "39:" in a card name stays part of the name.
+fn split_filter(token: &str) -> Option<(&str, Op, &str)> {
+    let key_len = token
+        .find(|c: char| !(c.is_ascii_alphabetic() || c == '_'))
+        .unwrap_or(token.len());
+    if key_len == 0 {
+        return None;
+    }
+    let (key, rest) = token.split_at(key_len);
+    let (op, value) = [
+        (">=", Op::Ge),
+        ("<=", Op::Le),
+        (">", Op::Gt),
+        ("<", Op::Lt),
+        ("=", Op::Eq),
+        (":", Op::Eq),
+    ]
+    .into_iter()
+    .find_map(|(symbol, op)| rest.strip_prefix(symbol).map(|value| (op, value)))?;
+    Some((key, op, value))
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+/// The choice of `T` named `value`, ignoring case and reading `_` as a space
+/// so `frame:effect_pendulum` works.
+fn choice<T: poise::ChoiceParameter>(key: &str, value: &str) -> Result<T, anyhow::Error> {
+    let wanted = value.replace('_', " ");
+    let choices = T::list();
+    choices
+        .iter()
+        .position(|choice| choice.name.eq_ignore_ascii_case(&wanted))
+        .and_then(T::from_index)
+        .ok_or_else(|| {
+            let names: Vec<_> = choices.iter().map(|choice| choice.name.as_str()).collect();
+            anyhow!("`{value}` is not a {key}. Use one of: {}", names.join(", "))
+        })
+}
+
+fn number<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, anyhow::Error> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("`{value}` is not a number for `{key}`"))
+}
+
+/// The inclusive `(min, max)` bounds for `op` applied to `n`. The bounds
+/// saturate, and are clamped to the field's range by the caller anyway.
+fn bounds(op: Op, n: i64) -> (Option<i64>, Option<i64>) {
+    match op {
+        Op::Eq => (Some(n), Some(n)),
+        Op::Lt => (None, Some(n.saturating_sub(1))),
+        Op::Le => (None, Some(n)),
+        Op::Gt => (Some(n.saturating_add(1)), None),
+        Op::Ge => (Some(n), None),
+    }
+}
+
+/// Like `bounds`, for levels and passcodes, which are never negative or
+/// above `u32::MAX`. Bounds past the end of that range are clamped to it,
+/// but ones before its start leave nothing to match, such as `passcode<0`,
+/// and are reported rather than quietly finding no cards.
+fn unsigned_bounds(
+    token: &str,
+    op: Op,
+    n: i64,
+) -> Result<(Option<u32>, Option<u32>), anyhow::Error> {
+    let (min, max) = bounds(op, n);
+    if max.is_some_and(|max| max < 0) || min.is_some_and(|min| min > u32::MAX.into()) {
+        return Err(anyhow!("`{token}` matches no cards"));
+    }
+    let clamp = |n: i64| n.clamp(0, u32::MAX.into()) as u32;
+    Ok((min.map(clamp), max.map(clamp)))
+}
+
+/// Parse a query string, see [`Query`].
+pub fn parse(input: &str) -> Result<Query, anyhow::Error> {
+    let mut query = Query::default();
+    let mut name = Vec::new();
+    let mut effect = Vec::new();
+    for token in tokenize(input) {
+        let Some((key, op, value)) = split_filter(&token) else {
+            name.push(token);
+            continue;
+        };
+        let key = key.to_ascii_lowercase();
+        let text_only = || match op {
+            Op::Eq => Ok(()),
+            op => Err(anyhow!("`{key}` can't be compared with `{}`", op.as_str())),
+        };
+        let filter = &mut query.filter;
+        match key.as_str() {
+            "name" => name.push(value.to_string()),
+            "effect" | "text" => effect.push(value.to_string()),
+            "type" => {
+                text_only()?;
+                filter.card_type = Some(choice::<CardType>("card type", unquote(value))?);
+            }
+            "frame" => {
+                text_only()?;
+                filter.frame_type = Some(choice::<FrameType>("frame", unquote(value))?);
+            }
+            "attr" | "attribute" => {
+                text_only()?;
+                filter.attribute = Some(choice::<Attribute>("attribute", unquote(value))?);
+            }
+            "race" => {
+                text_only()?;
+                filter.race = Some(unquote(value).to_string());
+            }
+            "archetype" => {
+                text_only()?;
+                match unquote(value) {
+                    value if value.eq_ignore_ascii_case("none") => filter.no_archetype = true,
+                    value => filter.archetype = Some(value.to_string()),
+                }
+            }
+            "category" => {
+                text_only()?;
+                filter.category = Some(unquote(value).to_string());
+            }
+            "full_type" => {
+                text_only()?;
+                filter.full_type = Some(unquote(value).to_string());
+            }
+            "link" => {
+                text_only()?;
+                filter.linkval = Some(number(&key, value)?);
+            }
+            "level" | "lv" | "rank" if op == Op::Eq => {
+                filter.levels.extend(parse_levels(Some(value))?);
+            }
+            "level" | "lv" | "rank" => {
+                let (min, max) = unsigned_bounds(&token, op, number(&key, value)?)?;
+                filter.min_level = min.or(filter.min_level);
+                filter.max_level = max.or(filter.max_level);
+            }
+            "passcode" if op == Op::Eq => {
+                let (min, max) = parse_passcode_range(Some(value))?;
+                filter.min_passcode = min.or(filter.min_passcode);
+                filter.max_passcode = max.or(filter.max_passcode);
+            }
+            "passcode" => {
+                let (min, max) = unsigned_bounds(&token, op, number(&key, value)?)?;
+                filter.min_passcode = min.or(filter.min_passcode);
+                filter.max_passcode = max.or(filter.max_passcode);
+            }
+            "atk" | "def" => {
+                let (min, max) = bounds(op, number(&key, value)?);
+                let clamp = |n: i64| n.clamp(i32::MIN.into(), i32::MAX.into()) as i32;
+                let range = if key == "atk" {
+                    &mut filter.atk
+                } else {
+                    &mut filter.def
+                };
+                range.min = min.map(clamp).or(range.min);
+                range.max = max.map(clamp).or(range.max);
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Unknown key `{key}`. Supported keys: {SUPPORTED_KEYS}"
+                ))
+            }
+        }
+    }
+    query.name = name.join(" * ");
+    query.effect = effect.join(" * ");
+    Ok(query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FilterArgs, StatRange};
+
+    #[test]
+    fn each_operator_sets_its_bounds() {
+        let query = parse("atk>=2000 def<=1500 level>3").unwrap();
+        assert_eq!(
+            (query.filter.atk.min, query.filter.atk.max),
+            (Some(2000), None)
+        );
+        assert_eq!(
+            (query.filter.def.min, query.filter.def.max),
+            (None, Some(1500))
+        );
+        assert_eq!(
+            (query.filter.min_level, query.filter.max_level),
+            (Some(4), None)
+        );
+
+        let query = parse("atk<1000 level:4").unwrap();
+        assert_eq!(query.filter.atk.max, Some(999));
+        assert_eq!(query.filter.levels, [4]);
+
+        let query = parse("def=0 passcode<100").unwrap();
+        assert_eq!(
+            (query.filter.def.min, query.filter.def.max),
+            (Some(0), Some(0))
+        );
+        assert_eq!(
+            (query.filter.min_passcode, query.filter.max_passcode),
+            (None, Some(99))
+        );
+    }
+
+    #[test]
+    fn reads_choices_and_text_filters() {
+        let query = parse("type:monster frame:effect_pendulum attr:DARK archetype:none").unwrap();
+        assert_eq!(query.filter.card_type, Some(CardType::Monster));
+        assert_eq!(query.filter.frame_type, Some(FrameType::EffectPendulum));
+        assert_eq!(query.filter.attribute, Some(Attribute::Dark));
+        assert!(query.filter.no_archetype);
+        assert_eq!(query.filter.archetype, None);
+
+        assert!(parse("attr:plaid").is_err());
+        assert!(parse("race>warrior").is_err());
+    }
+
+    #[test]
+    fn builds_the_same_filter_as_the_slash_options() {
+        let query = parse(
+            "type:monster attr:dark race:spellcaster archetype:\"dark magician\" level:6,7 \
+               atk>=2000 def<2500 passcode:40000000-50000000 magician",
+        )
+        .unwrap();
+        let expected = CardFilter {
+            card_type: Some(CardType::Monster),
+            attribute: Some(Attribute::Dark),
+            race: Some("spellcaster".to_string()),
+            archetype: Some("dark magician".to_string()),
+            levels: vec![6, 7],
+            atk: StatRange {
+                min: Some(2000),
+                max: None,
+            },
+            def: StatRange {
+                min: None,
+                max: Some(2499),
+            },
+            min_passcode: Some(40000000),
+            max_passcode: Some(50000000),
+            ..CardFilter::default()
+        };
+        assert_eq!(query.filter, expected);
+        assert_eq!(query.name, "magician");
+
+        let options = FilterArgs {
+            card_type: Some(CardType::Monster),
+            attribute: Some(Attribute::Dark),
+            race: Some("spellcaster".to_string()),
+            archetype: Some("dark magician".to_string()),
+            level: Some("6,7".to_string()),
+            min_atk: Some(2000),
+            max_def: Some(2499),
+            passcode: Some("40000000-50000000".to_string()),
+            ..FilterArgs::default()
+        };
+        assert_eq!(options.filter().unwrap(), expected);
+    }
+
+    #[test]
+    fn quotes_keep_spaces_together() {
+        let query = parse(r#"race:"sea serpent" "special summon" dragon effect:"draw 2""#).unwrap();
+        assert_eq!(query.filter.race.as_deref(), Some("sea serpent"));
+        assert_eq!(query.name, r#""special summon" * dragon"#);
+        assert_eq!(query.effect, r#""draw 2""#);
+
+        // An unbalanced quote is just a character.
+        let query = parse(r#"dark "magician"#).unwrap();
+        assert_eq!(query.name, r#"dark * "magician"#);
+    }
+
+    #[test]
+    fn bare_words_and_numbers_are_the_name() {
+        let query = parse("Number 39: Utopia").unwrap();
+        assert_eq!(query.name, "Number * 39: * Utopia");
+        assert!(parse("").unwrap().name.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let error = parse("colour:red").unwrap_err().to_string();
+        assert!(error.contains("Unknown key `colour`"), "{error}");
+        assert!(error.contains(SUPPORTED_KEYS), "{error}");
+    }
+
+    #[test]
+    fn extreme_bounds_saturate() {
+        let query = parse(&format!("atk<{} def>{}", i64::MIN, i64::MAX)).unwrap();
+        assert_eq!(query.filter.atk.max, Some(i32::MIN));
+        assert_eq!(query.filter.def.min, Some(i32::MAX));
+
+        let query = parse(&format!("level>=-5 passcode<={}", i64::MAX)).unwrap();
+        assert_eq!(query.filter.min_level, Some(0));
+        assert_eq!(query.filter.max_passcode, Some(u32::MAX));
+        let query = parse("level<1 passcode>=4294967295").unwrap();
+        assert_eq!(query.filter.max_level, Some(0));
+        assert_eq!(query.filter.min_passcode, Some(u32::MAX));
+
+        assert!(parse("atk>99999999999999999999").is_err());
+    }
+
+    #[test]
+    fn rejects_ranges_that_match_nothing() {
+        for token in [
+            "passcode<0".to_string(),
+            "level<0".to_string(),
+            "level<=-1".to_string(),
+            "passcode>4294967295".to_string(),
+            format!("level>{}", i64::MAX),
+            format!("passcode<{}", i64::MIN),
+        ] {
+            let error = parse(&format!("dark {token}")).unwrap_err().to_string();
+            assert_eq!(error, format!("`{token}` matches no cards"));
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::normalize::normalize_search_term;
+
+/// The most memory a compiled regex query may take. The regex crate matches
+/// in linear time, so capping the size also bounds the time spent per card.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// A parsed name or effect query.
+///
+/// `*` separates groups that must all match in order, like a glob, and binds
+/// looser than `|`, which separates alternatives within a group: `a * b | c`
+/// means `a` followed somewhere later by `b` or `c`. A leading or trailing
+/// `*` changes nothing, as the text may start and end with anything. An
+/// alternative starting with `-` matches when the text does not contain it
+/// anywhere, so it doesn't take part in the ordering. Double quotes make
+/// `*`, `|` and a leading `-` literal, so `"foo * bar"` is one phrase;
+/// unbalanced quotes are treated as ordinary punctuation. Empty alternatives
+/// and a lone `-` are ignored.
+///
+/// Alternatively the query is a single regular expression, see
+/// [`SearchTerms::regex`].
+#[derive(Debug, Default, Clone)]
+pub struct SearchTerms {
+    groups: Vec<Vec<Term>>,
+    /// Set instead of `groups` for regex queries.
+    pattern: Option<Regex>,
+    /// Terms only match whole words, see [`SearchTerms::whole_words`].
+    whole_words: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Term {
+    /// Already normalized with `normalize_search_term`.
+    text: String,
+    negated: bool,
+}
+
+/// Where the first match of `term` in `text` at or after `from` ends. With
+/// `whole_words`, a match must neither start nor end inside a word.
+fn find_term(text: &str, term: &str, from: usize, whole_words: bool) -> Option<usize> {
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    text[from..]
+        .match_indices(term)
+        .map(|(at, _)| (from + at, from + at + term.len()))
+        .find(|&(start, end)| {
+            !whole_words
+                || (is_boundary(text[..start].chars().next_back())
+                    && is_boundary(text[end..].chars().next()))
+        })
+        .map(|(_, end)| end)
+}
+
+/// Where the earliest ending match of any of `alternatives` at or after
+/// `cursor` ends. A negated alternative that holds matches without
+/// consuming any text.
+fn match_group(
+    alternatives: &[Term],
+    text: &str,
+    cursor: usize,
+    whole_words: bool,
+) -> Option<usize> {
+    if alternatives
+        .iter()
+        .any(|term| term.negated && find_term(text, &term.text, 0, whole_words).is_none())
+    {
+        return Some(cursor);
+    }
+    alternatives
+        .iter()
+        .filter(|term| !term.negated)
+        .filter_map(|term| find_term(text, &term.text, cursor, whole_words))
+        .min()
+}
+
+#[derive(Default)]
+struct TermBuilder {
+    text: String,
+    negated: bool,
+    started: bool,
+}
+
+impl TermBuilder {
+    fn finish(&mut self) -> Option<Term> {
+        let builder = std::mem::take(self);
+        let text = normalize_search_term(builder.text.trim());
+        (!text.is_empty()).then_some(Term {
+            text,
+            negated: builder.negated,
+        })
+    }
+}
+
+impl SearchTerms {
+    pub fn parse(query: &str) -> SearchTerms {
+        let quotes_balanced = query.matches('"').count().is_multiple_of(2);
+        let mut groups = vec![];
+        let mut alternatives = vec![];
+        let mut term = TermBuilder::default();
+        let mut in_quotes = false;
+
+        for c in query.chars() {
+            match c {
+                '"' if quotes_balanced => {
+                    in_quotes = !in_quotes;
+                    term.started = true;
+                }
+                '*' if !in_quotes => {
+                    alternatives.extend(term.finish());
+                    groups.push(std::mem::take(&mut alternatives));
+                }
+                '|' if !in_quotes => alternatives.extend(term.finish()),
+                '-' if !in_quotes && !term.started && !term.negated => term.negated = true,
+                c => {
+                    term.started |= !c.is_whitespace();
+                    term.text.push(c);
+                }
+            }
+        }
+        alternatives.extend(term.finish());
+        groups.push(alternatives);
+
+        groups.retain(|group| !group.is_empty());
+        SearchTerms {
+            groups,
+            pattern: None,
+            whole_words: false,
+        }
+    }
+
+    /// Make each term match only whole words of the normalized text, so
+    /// `set` no longer matches "reset". Punctuation is normalized away
+    /// first, so a term can't end partway through a word at an apostrophe.
+    pub fn whole_words(self) -> SearchTerms {
+        SearchTerms {
+            whole_words: true,
+            ..self
+        }
+    }
+
+    /// Compile `pattern` as a case-insensitive regular expression, matched
+    /// against the text as written rather than normalized. A blank pattern
+    /// matches everything, like an empty query.
+    pub fn regex(pattern: &str) -> Result<SearchTerms, regex::Error> {
+        if pattern.trim().is_empty() {
+            return Ok(SearchTerms::default());
+        }
+        let pattern = RegexBuilder::new(pattern)
+            .case_insensitive(true)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .build()?;
+        Ok(SearchTerms {
+            pattern: Some(pattern),
+            ..SearchTerms::default()
+        })
+    }
+
+    /// Whether the text satisfies every group in order, given both as written (`raw`)
+    /// and normalized with `normalize_search_term`.
+    pub fn matches(&self, raw: &str, normalized: &str) -> bool {
+        if let Some(pattern) = &self.pattern {
+            return pattern.is_match(raw);
+        }
+        // Taking the earliest ending match of each group leaves the most
+        // room for the groups after it.
+        let mut cursor = 0;
+        for alternatives in &self.groups {
+            match match_group(alternatives, normalized, cursor, self.whole_words) {
+                Some(end) => cursor = end,
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// The first term that must appear, used to rank matches by relevance.
+    pub fn first_positive(&self) -> Option<&str> {
+        self.groups
+            .iter()
+            .flatten()
+            .find(|term| !term.negated)
+            .map(|term| term.text.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(query: &str, text: &str) -> bool {
+        SearchTerms::parse(query).matches(text, &normalize_search_term(text))
+    }
+
+    #[test]
+    fn excludes_negated_terms() {
+        let effect = "Destroy 1 card on the field.";
+        let protected = "Destroy 1 monster. This card cannot be destroyed by battle.";
+        assert!(matches("destroy * -cannot be destroyed", effect));
+        assert!(!matches("destroy * -cannot be destroyed", protected));
+        assert!(matches("-cannot be destroyed", effect));
+        assert!(!matches("  -  cannot be destroyed  ", protected));
+    }
+
+    #[test]
+    fn excludes_negated_terms_in_names() {
+        assert!(matches("dragon * -blue", "Red-Eyes Black Dragon"));
+        assert!(!matches("dragon * -blue", "Blue-Eyes White Dragon"));
+        assert!(!matches("-eyes * dragon", "Red-Eyes Black Dragon"));
+        assert!(matches("-eyes * dragon", "Dragon Master Knight"));
+    }
+
+    #[test]
+    fn ignores_a_lone_minus() {
+        assert!(matches("-", "Dark Magician"));
+        assert!(matches("magician * - ", "Dark Magician"));
+        assert!(!matches("magician * - ", "Blue-Eyes White Dragon"));
+    }
+
+    #[test]
+    fn alternatives_bind_tighter_than_groups() {
+        let query = "banish * shuffle | return";
+        assert!(matches(
+            query,
+            "Banish 1 card, then shuffle it into the Deck."
+        ));
+        assert!(matches(query, "Banish 1 card, then return it to the hand."));
+        assert!(!matches(query, "Shuffle 1 card into the Deck."));
+        assert!(!matches(query, "Return 1 banished card."));
+
+        assert!(matches("a * b | c", "a then c"));
+        assert!(matches("a * b | c", "a then b"));
+        assert!(!matches("a * b | c", "b then c"));
+    }
+
+    #[test]
+    fn ignores_empty_alternatives() {
+        assert!(matches("| dark |", "Dark Magician"));
+        assert!(!matches("| dark |", "Blue-Eyes White Dragon"));
+        assert!(matches("||", "Blue-Eyes White Dragon"));
+    }
+
+    #[test]
+    fn quoted_phrases_are_literal() {
+        let query = r#""Once per turn:" * "foo * bar""#;
+        assert!(matches(query, "Once per turn: you can foo * bar."));
+        assert!(!matches(query, "Once per turn: you can foo, then bar."));
+
+        assert!(matches(r#""a | b""#, "a | b"));
+        assert!(!matches(r#""a | b""#, "a"));
+        assert!(matches(r#""-1 card""#, "Draw -1 card."));
+        assert!(!matches(r#""per turn once""#, "Once per turn"));
+    }
+
+    #[test]
+    fn unbalanced_quotes_are_punctuation() {
+        assert!(matches(r#""dark magician"#, "Dark Magician"));
+        assert!(matches(r#"dark * "magician"#, "Dark Magician"));
+        assert!(!matches(r#"dark * "dragon"#, "Dark Magician"));
+    }
+
+    #[test]
+    fn groups_match_in_order() {
+        assert!(matches("dark * magician", "Dark Magician"));
+        assert!(!matches("magician * dark", "Dark Magician"));
+        assert!(matches("magician * dark", "Magician of Dark Illusion"));
+        // Each group starts after the previous one ends.
+        assert!(!matches("dark * dark", "Dark Magician"));
+        assert!(matches("dark * dark", "Dark Magician of Dark Illusion"));
+    }
+
+    #[test]
+    fn whole_words_skip_matches_inside_words() {
+        let whole = |query: &str, text: &str| {
+            SearchTerms::parse(query)
+                .whole_words()
+                .matches(text, &normalize_search_term(text))
+        };
+        assert!(matches("set", "Reset the field."));
+        assert!(!whole("set", "Reset the field."));
+        assert!(whole("set", "Set 1 Spell card."));
+        assert!(whole("set * field", "Reset, then set it on the field."));
+        assert!(!whole("dark * -set", "Dark Magician, this card is set."));
+        assert!(whole("dark * -set", "Dark Magician resets."));
+        // A later whole word match still counts after one inside a word.
+        assert!(whole("magician", "Magicians and a Magician"));
+    }
+
+    #[test]
+    fn regexes_ignore_case_and_match_the_raw_text() {
+        let regex = |pattern: &str, text: &str| {
+            SearchTerms::regex(pattern)
+                .unwrap()
+                .matches(text, &normalize_search_term(text))
+        };
+        assert!(regex("^dark magician$", "Dark Magician"));
+        assert!(!regex("^dark magician$", "Dark Magician Girl"));
+        // Punctuation is kept, unlike in normalized text.
+        assert!(regex(r"Red-Eyes", "Red-Eyes Black Dragon"));
+        assert!(!regex(r"red eyes", "Red-Eyes Black Dragon"));
+        assert!(regex(r"\d{4} ATK", "Gains 1000 ATK."));
+        assert!(regex("  ", "anything"));
+        assert!(SearchTerms::regex("(unclosed").is_err());
+    }
+
+    #[test]
+    fn leading_and_trailing_stars_match_anything() {
+        assert!(matches("*magician", "Dark Magician"));
+        assert!(matches("dark*", "Dark Magician"));
+        assert!(matches("* magician *", "Dark Magician Girl"));
+        assert!(matches("*", "Dark Magician"));
+    }
+}
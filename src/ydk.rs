@@ -0,0 +1,84 @@
+use anyhow::{anyhow, Context};
+
+/// The passcodes listed in a `.ydk` deck file, as exported by EDOPro.
+///
+/// ```text
+/// #created by ...
+/// #main
+/// 89631139
+/// #extra
+/// 44508094
+/// !side
+/// 14558127
+/// ```
+#[derive(Debug, Default)]
+pub struct YdkDeck {
+    pub main: Vec<u32>,
+    pub extra: Vec<u32>,
+    pub side: Vec<u32>,
+}
+
+#[derive(Clone, Copy)]
+enum Section {
+    Main,
+    Extra,
+    Side,
+}
+
+impl YdkDeck {
+    pub fn parse(text: &str) -> Result<YdkDeck, anyhow::Error> {
+        let mut deck = YdkDeck::default();
+        let mut section = None;
+        for (line_number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            match line {
+                "" => continue,
+                "#main" => section = Some(Section::Main),
+                "#extra" => section = Some(Section::Extra),
+                "!side" => section = Some(Section::Side),
+                // Any other `#` line is a comment, e.g. `#created by`.
+                _ if line.starts_with('#') => continue,
+                _ => {
+                    let passcode = line.parse().with_context(|| {
+                        format!("Line {} is not a passcode: `{line}`", line_number + 1)
+                    })?;
+                    match section {
+                        Some(Section::Main) => deck.main.push(passcode),
+                        Some(Section::Extra) => deck.extra.push(passcode),
+                        Some(Section::Side) => deck.side.push(passcode),
+                        None => {
+                            return Err(anyhow!(
+                                "Passcode on line {} is not under #main, #extra or !side",
+                                line_number + 1
+                            ))
+                        }
+                    }
+                }
+            }
+        }
+        Ok(deck)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_each_section() {
+        let deck = YdkDeck::parse(
+            "#created by someone\r\n#main\r\n89631139\r\n89631139\r\n\r\n#extra\r\n44508094\r\n!side\r\n  14558127  \r\n",
+        )
+        .unwrap();
+        assert_eq!(deck.main, [89631139, 89631139]);
+        assert_eq!(deck.extra, [44508094]);
+        assert_eq!(deck.side, [14558127]);
+    }
+
+    #[test]
+    fn rejects_bad_lines() {
+        let error = YdkDeck::parse("#main\n89631139\nDark Magician\n").unwrap_err();
+        assert!(error.to_string().contains("Line 3"), "{error}");
+        assert!(YdkDeck::parse("89631139\n#main\n").is_err());
+    }
+}
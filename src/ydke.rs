@@ -0,0 +1,105 @@
+use anyhow::{anyhow, Context};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+const PREFIX: &str = "ydke://";
+
+/// The passcodes in a `ydke://` deck code, as shared by EDOPro and various
+/// web deck builders.
+///
+/// The code is `ydke://<main>!<extra>!<side>!`, where each section is the
+/// base64 encoding of little-endian `u32` passcodes.
+#[derive(Debug, Default)]
+pub struct YdkeDeck {
+    pub main: Vec<u32>,
+    pub extra: Vec<u32>,
+    pub side: Vec<u32>,
+}
+
+impl YdkeDeck {
+    pub fn is_ydke(code: &str) -> bool {
+        code.trim().starts_with(PREFIX)
+    }
+
+    pub fn parse(code: &str) -> Result<YdkeDeck, anyhow::Error> {
+        let body = code
+            .trim()
+            .strip_prefix(PREFIX)
+            .ok_or_else(|| anyhow!("ydke codes must start with {PREFIX}"))?;
+        let mut sections = body.split('!');
+        let mut next = |name: &str| -> Result<Vec<u32>, anyhow::Error> {
+            let section = sections
+                .next()
+                .ok_or_else(|| anyhow!("ydke code is missing the {name} section"))?;
+            decode_section(section).with_context(|| format!("While decoding the {name} section"))
+        };
+        Ok(YdkeDeck {
+            main: next("main")?,
+            extra: next("extra")?,
+            side: next("side")?,
+        })
+    }
+
+    pub fn encode(&self) -> String {
+        format!(
+            "{PREFIX}{}!{}!{}!",
+            encode_section(&self.main),
+            encode_section(&self.extra),
+            encode_section(&self.side)
+        )
+    }
+}
+
+fn encode_section(passcodes: &[u32]) -> String {
+    let bytes: Vec<u8> = passcodes.iter().flat_map(|p| p.to_le_bytes()).collect();
+    STANDARD.encode(bytes)
+}
+
+fn decode_section(section: &str) -> Result<Vec<u32>, anyhow::Error> {
+    let bytes = STANDARD.decode(section)?;
+    if bytes.len() % 4 != 0 {
+        return Err(anyhow!("section length is not a multiple of 4 bytes"));
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_little_endian_passcodes() {
+        let deck = YdkeDeck::parse(" ydke://rvTMAqOpVwU=!viOnAg==!! ").unwrap();
+        assert_eq!(deck.main, [46986414, 89631139]);
+        assert_eq!(deck.extra, [44508094]);
+        assert!(deck.side.is_empty());
+    }
+
+    #[test]
+    fn round_trips() {
+        let deck = YdkeDeck {
+            main: vec![46986414, 46986414, 89631139],
+            extra: vec![44508094],
+            side: vec![u32::MAX, 0],
+        };
+        let code = deck.encode();
+        assert!(YdkeDeck::is_ydke(&code));
+        let decoded = YdkeDeck::parse(&code).unwrap();
+        assert_eq!(decoded.main, deck.main);
+        assert_eq!(decoded.extra, deck.extra);
+        assert_eq!(decoded.side, deck.side);
+        assert_eq!(YdkeDeck::default().encode(), "ydke://!!!");
+    }
+
+    #[test]
+    fn rejects_malformed_codes() {
+        assert!(!YdkeDeck::is_ydke("https://www.duelingbook.com/deck?id=1"));
+        assert!(YdkeDeck::parse("rvTMAg==!!!").is_err());
+        assert!(YdkeDeck::parse("ydke://rvTMAg==").is_err());
+        assert!(YdkeDeck::parse("ydke://not base64!!!").is_err());
+        // Three bytes can't be a passcode.
+        assert!(YdkeDeck::parse("ydke://AQID!!!").is_err());
+    }
+}